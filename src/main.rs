@@ -3,35 +3,52 @@ mod utils;
 
 fn main() {
     let argv: Vec<String> = std::env::args().collect();
-    // expect three arguments: day number, part number, input file path
+    // expect three arguments: day number, part number, input file path,
+    // optionally followed by solution-specific flags
     let day_num = argv[1].parse::<isize>().expect("arg 1 was not a number");
     let part = argv[2].parse::<isize>().expect("arg 2 was not a number");
     let input_path = &argv[3];
+    let known_flags = match day_num {
+        1 => solutions::day01::FLAGS,
+        2 => solutions::day02::FLAGS,
+        3 => solutions::day03::FLAGS,
+        4 => solutions::day04::FLAGS,
+        5 => solutions::day05::FLAGS,
+        6 => solutions::day06::FLAGS,
+        7 => solutions::day07::FLAGS,
+        8 => solutions::day08::FLAGS,
+        9 => solutions::day09::FLAGS,
+        10 => solutions::day10::FLAGS,
+        11 => solutions::day11::FLAGS,
+        12 => solutions::day12::FLAGS,
+        _ => &[],
+    };
+    let options = utils::Options::from_args(&argv[4..], known_flags);
     match (day_num, part) {
-        (1, 1) => solutions::day01::part1(input_path),
-        (1, 2) => solutions::day01::part2(input_path),
-        (2, 1) => solutions::day02::part1(input_path),
-        (2, 2) => solutions::day02::part2(input_path),
-        (3, 1) => solutions::day03::part1(input_path),
-        (3, 2) => solutions::day03::part2(input_path),
-        (4, 1) => solutions::day04::part1(input_path),
-        (4, 2) => solutions::day04::part2(input_path),
-        (5, 1) => solutions::day05::part1(input_path),
-        (5, 2) => solutions::day05::part2(input_path),
-        (6, 1) => solutions::day06::part1(input_path),
-        (6, 2) => solutions::day06::part2(input_path),
-        (7, 1) => solutions::day07::part1(input_path),
-        (7, 2) => solutions::day07::part2(input_path),
-        (8, 1) => solutions::day08::part1(input_path),
-        (8, 2) => solutions::day08::part2(input_path),
-        (9, 1) => solutions::day09::part1(input_path),
-        (9, 2) => solutions::day09::part2(input_path),
-        (10, 1) => solutions::day10::part1(input_path),
-        (10, 2) => solutions::day10::part2(input_path),
-        (11, 1) => solutions::day11::part1(input_path),
-        (11, 2) => solutions::day11::part2(input_path),
-        (12, 1) => solutions::day12::part1(input_path),
-        (12, 2) => solutions::day12::part2(input_path),
+        (1, 1) => solutions::day01::part1(input_path, &options),
+        (1, 2) => solutions::day01::part2(input_path, &options),
+        (2, 1) => solutions::day02::part1(input_path, &options),
+        (2, 2) => solutions::day02::part2(input_path, &options),
+        (3, 1) => solutions::day03::part1(input_path, &options),
+        (3, 2) => solutions::day03::part2(input_path, &options),
+        (4, 1) => solutions::day04::part1(input_path, &options),
+        (4, 2) => solutions::day04::part2(input_path, &options),
+        (5, 1) => solutions::day05::part1(input_path, &options),
+        (5, 2) => solutions::day05::part2(input_path, &options),
+        (6, 1) => solutions::day06::part1(input_path, &options),
+        (6, 2) => solutions::day06::part2(input_path, &options),
+        (7, 1) => solutions::day07::part1(input_path, &options),
+        (7, 2) => solutions::day07::part2(input_path, &options),
+        (8, 1) => solutions::day08::part1(input_path, &options),
+        (8, 2) => solutions::day08::part2(input_path, &options),
+        (9, 1) => solutions::day09::part1(input_path, &options),
+        (9, 2) => solutions::day09::part2(input_path, &options),
+        (10, 1) => solutions::day10::part1(input_path, &options),
+        (10, 2) => solutions::day10::part2(input_path, &options),
+        (11, 1) => solutions::day11::part1(input_path, &options),
+        (11, 2) => solutions::day11::part2(input_path, &options),
+        (12, 1) => solutions::day12::part1(input_path, &options),
+        (12, 2) => solutions::day12::part2(input_path, &options),
         (13, 1) => solutions::day13::part1(input_path),
         (13, 2) => solutions::day13::part2(input_path),
         (14, 1) => solutions::day14::part1(input_path),
@@ -1,36 +1,133 @@
 use std::{
-    cmp::Reverse,
     fs::File,
     io::{BufRead, BufReader},
 };
 
-fn solve(input_path: &str, n_max: usize) {
+use crate::utils::{Options, TopK};
+
+pub const FLAGS: &[&str] = &["--stats"];
+
+fn solve(input_path: &str, n_max: usize, options: &Options) {
     let file = File::open(input_path).expect("could not open file");
     let reader = BufReader::new(file);
-    let mut elves = Vec::new();
-    let mut elf_calories = 0;
+
+    let mut top_elves = TopK::new(n_max);
+    let mut stats = Stats::default();
+    for_each_elf(reader, |elf_num, elf_calories| {
+        top_elves.push((elf_calories, elf_num));
+        stats.add(elf_calories);
+    });
+
+    let top_elves = top_elves.into_sorted_vec();
+    let total_calories: usize = top_elves.iter().map(|&(calories, _)| calories).sum();
+
+    if options.flag("--stats") {
+        for &(calories, elf_num) in &top_elves {
+            println!("elf {}: {}", elf_num, calories);
+        }
+        match stats.mean() {
+            Some(mean) => println!(
+                "{} elves, min {}, max {}, mean {:.1}",
+                stats.count, stats.min, stats.max, mean
+            ),
+            None => println!("0 elves"),
+        }
+    }
+
+    println!("{}", total_calories);
+}
+
+pub fn part1(input_path: &str, options: &Options) {
+    solve(input_path, 1, options)
+}
+
+pub fn part2(input_path: &str, options: &Options) {
+    solve(input_path, 3, options)
+}
+
+/// Calls `f` with the (0-based) index and total calories of every elf.
+/// Groups are separated by blank lines; the last group does not need to be
+/// followed by one.
+fn for_each_elf<R, F>(reader: R, mut f: F)
+where
+    R: BufRead,
+    F: FnMut(usize, usize),
+{
+    let mut elf_num = 0;
+    let mut elf_calories: Option<usize> = None;
 
     for line_result in reader.lines() {
         let line = line_result.expect("failed to read line");
-        if line.len() == 0 {
-            elves.push(elf_calories);
-            elf_calories = 0;
+        if line.is_empty() {
+            if let Some(calories) = elf_calories.take() {
+                f(elf_num, calories);
+                elf_num += 1;
+            }
         } else {
             let calories = line.parse::<usize>().expect("not a number");
-            elf_calories += calories;
+            *elf_calories.get_or_insert(0) += calories;
         }
     }
 
-    elves.sort_by_key(|&e| Reverse(e));
-    let total_calories: usize = elves.iter().take(n_max).sum();
+    if let Some(calories) = elf_calories {
+        f(elf_num, calories);
+    }
+}
 
-    println!("{}", total_calories);
+#[derive(Default)]
+struct Stats {
+    count: usize,
+    sum: usize,
+    min: usize,
+    max: usize,
 }
 
-pub fn part1(input_path: &str) {
-    solve(input_path, 1)
+impl Stats {
+    fn add(&mut self, value: usize) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    /// None when no values were added
+    fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
 }
 
-pub fn part2(input_path: &str) {
-    solve(input_path, 3)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn elves(input: &[u8]) -> Vec<(usize, usize)> {
+        let mut elves = Vec::new();
+        for_each_elf(input, |elf_num, calories| elves.push((elf_num, calories)));
+        elves
+    }
+
+    #[test]
+    fn test_final_group() {
+        assert_eq!(elves(b"1\n2\n\n3\n\n"), vec![(0, 3), (1, 3)]);
+        assert_eq!(elves(b"1\n2\n\n3\n"), vec![(0, 3), (1, 3)]);
+        assert_eq!(elves(b"1\n2\n\n3"), vec![(0, 3), (1, 3)]);
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.mean(), None);
+        for value in [6000, 4000, 11000, 24000, 10000] {
+            stats.add(value);
+        }
+        assert_eq!(stats.count, 5);
+        assert_eq!(stats.min, 4000);
+        assert_eq!(stats.max, 24000);
+        assert_eq!(stats.mean(), Some(11000.0));
+    }
 }
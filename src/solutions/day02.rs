@@ -3,99 +3,183 @@ use std::{
     io::{BufRead, BufReader},
 };
 
-pub fn part1(input_path: &str) {
-    let file = File::open(input_path).expect("could not open input file");
-    let reader = BufReader::new(file);
+use crate::utils::Options;
 
-    let mut total_score: usize = 0;
-    for split_result in reader.split(b'\n') {
-        let buf = split_result.expect("failed read");
-        assert!(buf.len() == 3, "bad line");
+pub const FLAGS: &[&str] = &[
+    "--opponent-letters=",
+    "--optimize",
+    "--outcome-scores=",
+    "--response-letters=",
+    "--scores=",
+];
 
-        assert!(buf[1] == b' ');
-        let opponent = match buf[0] {
-            b'A' => Shape::Rock,
-            b'B' => Shape::Paper,
-            b'C' => Shape::Scissors,
-            _ => panic!("unexpected byte {}", buf[0]),
-        };
-        let mine = match buf[2] {
-            b'X' => Shape::Rock,
-            b'Y' => Shape::Paper,
-            b'Z' => Shape::Scissors,
-            _ => panic!("unexpected byte {}", buf[2]),
+pub fn part1(input_path: &str, options: &Options) {
+    let game = Game::from_options(options);
+    let optimize = options.flag("--optimize");
+
+    let mut total_score: usize = 0;
+    for (opponent, response_letter) in read_rounds(input_path) {
+        let opponent = game.opponent_shape(opponent);
+        let mine = if optimize {
+            // ignore the strategy guide, and pick the best response
+            game.best_response(opponent)
+        } else {
+            game.response_shape(response_letter)
         };
 
-        let outcome = shapes_to_outcome(mine, opponent);
-        total_score += outcome_score(outcome);
-        total_score += shape_score(mine);
+        let outcome = game.outcome(mine, opponent);
+        total_score += game.round_score(mine, outcome);
     }
 
     println!("score: {}", total_score);
 }
 
-pub fn part2(input_path: &str) {
-    let file = File::open(input_path).expect("could not open input file");
-    let reader = BufReader::new(file);
+pub fn part2(input_path: &str, options: &Options) {
+    let game = Game::from_options(options);
 
     let mut total_score: usize = 0;
-    for split_result in reader.split(b'\n') {
-        let buf = split_result.expect("failed read");
-        assert!(buf.len() == 3, "bad line");
-
-        assert!(buf[1] == b' ');
-        let opponent = match buf[0] {
-            b'A' => Shape::Rock,
-            b'B' => Shape::Paper,
-            b'C' => Shape::Scissors,
-            _ => panic!("unexpected byte {}", buf[0]),
-        };
-        let outcome = match buf[2] {
+    for (opponent, outcome_letter) in read_rounds(input_path) {
+        let opponent = game.opponent_shape(opponent);
+        let outcome = match outcome_letter {
             b'X' => Outcome::Loss,
             b'Y' => Outcome::Tie,
             b'Z' => Outcome::Win,
-            _ => panic!("unexpected byte {}", buf[2]),
+            _ => panic!("unexpected byte {}", outcome_letter),
         };
 
-        let mine = shape_for_outcome(outcome, opponent);
-        total_score += outcome_score(outcome);
-        total_score += shape_score(mine);
+        let mine = game.shape_for_outcome(outcome, opponent);
+        total_score += game.round_score(mine, outcome);
     }
 
     println!("score: {}", total_score);
 }
 
-const NUM_SHAPES: usize = 3;
+fn read_rounds(input_path: &str) -> impl Iterator<Item = (u8, u8)> {
+    let file = File::open(input_path).expect("could not open input file");
+    let reader = BufReader::new(file);
+
+    reader.split(b'\n').map(|split_result| {
+        let buf = split_result.expect("failed read");
+        assert!(buf.len() == 3, "bad line");
+        assert!(buf[1] == b' ');
+        (buf[0], buf[2])
+    })
+}
 
+/// A shape, identified by its index in the game
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Shape {
-    Rock,
-    Paper,
-    Scissors,
+struct Shape(usize);
+
+/// A cyclic tournament with an odd number of shapes, where every shape
+/// beats the (n - 1) / 2 shapes preceding it (wrapping around), and loses
+/// to the ones following it.
+/// With three shapes, this is rock-paper-scissors.
+struct Game {
+    shape_scores: Vec<usize>,
+    opponent_letters: Vec<u8>,
+    response_letters: Vec<u8>,
+    // indexed by Outcome::to_num
+    outcome_scores: [usize; 3],
 }
 
-fn shape_to_num(shape: Shape) -> usize {
-    match shape {
-        Shape::Rock => 0,
-        Shape::Paper => 1,
-        Shape::Scissors => 2,
+impl Game {
+    fn classic() -> Self {
+        Game {
+            // rock, paper, scissors
+            shape_scores: vec![1, 2, 3],
+            opponent_letters: b"ABC".to_vec(),
+            response_letters: b"XYZ".to_vec(),
+            outcome_scores: [3, 6, 0],
+        }
     }
-}
 
-fn num_to_shape(num: usize) -> Shape {
-    match num {
-        0 => Shape::Rock,
-        1 => Shape::Paper,
-        2 => Shape::Scissors,
-        _ => panic!("bad shape num"),
+    /// Configures the game from the command line, e.g. for
+    /// rock-paper-scissors-lizard-spock (in an order where every shape beats
+    /// the two shapes before it):
+    /// `--scores=1,5,2,4,3 --opponent-letters=ABCDE --response-letters=VWXYZ`
+    fn from_options(options: &Options) -> Self {
+        let mut game = Game::classic();
+        if let Some(scores) = options.parse_list("--scores") {
+            game.shape_scores = scores;
+        }
+        if let Some(letters) = options.value("--opponent-letters") {
+            game.opponent_letters = letters.as_bytes().to_vec();
+        }
+        if let Some(letters) = options.value("--response-letters") {
+            game.response_letters = letters.as_bytes().to_vec();
+        }
+        if let Some(scores) = options.parse_list::<usize>("--outcome-scores") {
+            assert!(scores.len() == 3, "expected loss, tie and win scores");
+            game.outcome_scores = [scores[1], scores[2], scores[0]];
+        }
+
+        let num_shapes = game.num_shapes();
+        assert!(num_shapes % 2 == 1, "number of shapes should be odd");
+        assert!(
+            game.opponent_letters.len() == num_shapes,
+            "wrong number of opponent letters"
+        );
+        assert!(
+            game.response_letters.len() == num_shapes,
+            "wrong number of response letters"
+        );
+        game
     }
-}
 
-fn shape_score(shape: Shape) -> usize {
-    match shape {
-        Shape::Rock => 1,
-        Shape::Paper => 2,
-        Shape::Scissors => 3,
+    fn num_shapes(&self) -> usize {
+        self.shape_scores.len()
+    }
+
+    fn shapes(&self) -> impl Iterator<Item = Shape> {
+        (0..self.num_shapes()).map(Shape)
+    }
+
+    fn opponent_shape(&self, letter: u8) -> Shape {
+        self.opponent_letters
+            .iter()
+            .position(|&l| l == letter)
+            .map(Shape)
+            .unwrap_or_else(|| panic!("unexpected byte {}", letter))
+    }
+
+    fn response_shape(&self, letter: u8) -> Shape {
+        self.response_letters
+            .iter()
+            .position(|&l| l == letter)
+            .map(Shape)
+            .unwrap_or_else(|| panic!("unexpected byte {}", letter))
+    }
+
+    fn round_score(&self, mine: Shape, outcome: Outcome) -> usize {
+        self.shape_scores[mine.0] + self.outcome_scores[outcome_to_num(outcome)]
+    }
+
+    fn outcome(&self, mine: Shape, opponent: Shape) -> Outcome {
+        let num_shapes = self.num_shapes();
+        let diff = (num_shapes + mine.0 - opponent.0) % num_shapes;
+        if diff == 0 {
+            Outcome::Tie
+        } else if diff <= num_shapes / 2 {
+            Outcome::Win
+        } else {
+            Outcome::Loss
+        }
+    }
+
+    /// With more than three shapes, several shapes may give the desired
+    /// outcome; this picks the one that scores best.
+    fn shape_for_outcome(&self, outcome: Outcome, opponent: Shape) -> Shape {
+        self.shapes()
+            .filter(|&shape| self.outcome(shape, opponent) == outcome)
+            .max_by_key(|&shape| self.shape_scores[shape.0])
+            .unwrap()
+    }
+
+    /// The response that maximizes the score for this round
+    fn best_response(&self, opponent: Shape) -> Shape {
+        self.shapes()
+            .max_by_key(|&shape| self.round_score(shape, self.outcome(shape, opponent)))
+            .unwrap()
     }
 }
 
@@ -114,48 +198,52 @@ fn outcome_to_num(outcome: Outcome) -> usize {
     }
 }
 
-fn num_to_outcome(num: usize) -> Outcome {
-    match num {
-        0 => Outcome::Tie,
-        1 => Outcome::Win,
-        2 => Outcome::Loss, // -1 mod 3
-        _ => panic!("invalid outcome num"),
-    }
-}
-
-fn outcome_score(outcome: Outcome) -> usize {
-    match outcome {
-        Outcome::Win => 6,
-        Outcome::Tie => 3,
-        Outcome::Loss => 0,
-    }
-}
-
-fn shapes_to_outcome(mine: Shape, opponent: Shape) -> Outcome {
-    num_to_outcome((NUM_SHAPES + shape_to_num(mine) - shape_to_num(opponent)) % NUM_SHAPES)
-}
-
-fn shape_for_outcome(outcome: Outcome, opponent: Shape) -> Shape {
-    num_to_shape((shape_to_num(opponent) + outcome_to_num(outcome)) % NUM_SHAPES)
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
     use Outcome::*;
-    use Shape::*;
+
+    // the shapes of the classic game
+    const ROCK: Shape = Shape(0);
+    const PAPER: Shape = Shape(1);
+    const SCISSORS: Shape = Shape(2);
 
     #[test]
     fn test_round_outcome_score() {
-        assert_eq!(shapes_to_outcome(Rock, Scissors), Win);
-        assert_eq!(shapes_to_outcome(Paper, Paper), Tie);
-        assert_eq!(shapes_to_outcome(Paper, Scissors), Loss);
+        let game = Game::classic();
+        assert_eq!(game.outcome(ROCK, SCISSORS), Win);
+        assert_eq!(game.outcome(PAPER, PAPER), Tie);
+        assert_eq!(game.outcome(PAPER, SCISSORS), Loss);
     }
 
     #[test]
     fn test_shape_for_outcome() {
-        assert_eq!(shape_for_outcome(Win, Rock), Paper);
-        assert_eq!(shape_for_outcome(Loss, Rock), Scissors);
-        assert_eq!(shape_for_outcome(Tie, Paper), Paper);
+        let game = Game::classic();
+        assert_eq!(game.shape_for_outcome(Win, ROCK), PAPER);
+        assert_eq!(game.shape_for_outcome(Loss, ROCK), SCISSORS);
+        assert_eq!(game.shape_for_outcome(Tie, PAPER), PAPER);
+    }
+
+    #[test]
+    fn test_five_shapes() {
+        // rock, spock, paper, lizard, scissors
+        let game = Game {
+            shape_scores: vec![1, 5, 2, 4, 3],
+            opponent_letters: b"ABCDE".to_vec(),
+            response_letters: b"VWXYZ".to_vec(),
+            outcome_scores: [3, 6, 0],
+        };
+        let [rock, spock, paper, lizard, scissors] = [0, 1, 2, 3, 4].map(Shape);
+        assert_eq!(game.outcome(paper, rock), Win);
+        assert_eq!(game.outcome(spock, rock), Win);
+        assert_eq!(game.outcome(lizard, spock), Win);
+        assert_eq!(game.outcome(rock, lizard), Win);
+        assert_eq!(game.outcome(scissors, rock), Loss);
+        assert_eq!(game.outcome(lizard, scissors), Loss);
+        // both spock and paper beat rock, spock scores higher
+        assert_eq!(game.shape_for_outcome(Win, rock), spock);
+        assert_eq!(game.best_response(rock), spock);
+        // rock and scissors both beat lizard, scissors scores higher
+        assert_eq!(game.best_response(lizard), scissors);
     }
 }
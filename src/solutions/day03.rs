@@ -1,66 +1,115 @@
 use std::{
-    collections::HashMap,
     fs::File,
     io::{BufRead, BufReader},
 };
 
-use std::collections::HashSet;
+use crate::utils::Options;
 
-pub fn part1(input_path: &str) {
+pub const FLAGS: &[&str] = &["--group-size=", "--report"];
+
+pub fn part1(input_path: &str, options: &Options) {
     let file = File::open(input_path).expect("could not open input file");
     let reader = BufReader::new(file);
 
     let mut total_priority = 0;
-    for line_result in reader.lines() {
+    for (line_num, line_result) in reader.lines().enumerate() {
         let line = line_result.expect("could not read line");
-        total_priority += rucksack_priority(&line);
+        let common = compartment_common_items(&line);
+        if options.flag("--report") {
+            report_group(line_num, common);
+        }
+        total_priority += common.priority_sum();
     }
 
     println!("total: {}", total_priority);
 }
 
-pub fn part2(input_path: &str) {
-    let n_elves = 3;
+pub fn part2(input_path: &str, options: &Options) {
+    let n_elves: usize = options.parse_or("--group-size", 3);
+    assert!(n_elves > 0, "--group-size must be positive");
 
     let file = File::open(input_path).expect("could not open input file");
     let reader = BufReader::new(file);
 
+    // only one group of rucksacks is held in memory at a time
+    let mut group = Vec::with_capacity(n_elves);
+    let mut group_num = 0;
     let mut total_priority = 0;
-    let mut item_map: HashMap<char, usize> = HashMap::new();
-    for (line_num, line_result) in reader.lines().enumerate() {
-        let nth_line = line_num % n_elves;
-
-        let line = line_result.expect("could not read line");
-        for c in line.chars() {
-            if item_map.get(&c).unwrap_or(&0) == &nth_line {
-                item_map.insert(c, nth_line + 1);
-            }
+    for line_result in reader.lines() {
+        group.push(line_result.expect("could not read line"));
+        if group.len() < n_elves {
+            continue;
         }
 
-        // last line, process group
-        if nth_line == n_elves - 1 {
-            for (c, n) in item_map.drain() {
-                if n == n_elves {
-                    total_priority += char_priority(c);
-                }
-            }
+        let badge = common_items(group.iter().map(|line| line.as_str()));
+        if options.flag("--report") {
+            report_group(group_num, badge);
         }
+        total_priority += badge.priority_sum();
+        group.clear();
+        group_num += 1;
     }
+    assert!(
+        group.is_empty(),
+        "number of rucksacks is not a multiple of the group size"
+    );
 
     println!("total {}", total_priority);
 }
 
-fn rucksack_priority(rucksack_str: &str) -> usize {
-    let (fst, snd) = split_compartiments(rucksack_str);
+fn report_group(group_num: usize, common: ItemSet) {
+    let items: String = common.items().collect();
+    println!("{}: {} ({})", group_num, items, common.priority_sum());
+}
+
+/// A set of rucksack items, where bit `n` is set when the item with
+/// priority `n` is present.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ItemSet(u64);
+
+impl ItemSet {
+    // priorities range from 1 to 52
+    const ALL: ItemSet = ItemSet(((1 << 52) - 1) << 1);
+
+    fn from_items(items: &str) -> Self {
+        ItemSet(
+            items
+                .chars()
+                .fold(0, |set, c| set | (1 << char_priority(c))),
+        )
+    }
+
+    fn intersection(self, other: ItemSet) -> ItemSet {
+        ItemSet(self.0 & other.0)
+    }
+
+    fn priority_sum(self) -> usize {
+        self.priorities().sum()
+    }
 
-    let fst_set: HashSet<char> = HashSet::from_iter(fst.chars());
-    let snd_set: HashSet<char> = HashSet::from_iter(snd.chars());
+    fn priorities(self) -> impl Iterator<Item = usize> {
+        (1..=52).filter(move |&priority| self.0 & (1 << priority) != 0)
+    }
+
+    fn items(self) -> impl Iterator<Item = char> {
+        self.priorities().map(priority_char)
+    }
+}
 
-    fst_set
-        .intersection(&snd_set)
-        .cloned()
-        .map(char_priority)
-        .sum()
+/// The items that are present in every rucksack of the group
+fn common_items<'a, I>(group: I) -> ItemSet
+where
+    I: IntoIterator<Item = &'a str>,
+{
+    group
+        .into_iter()
+        .map(ItemSet::from_items)
+        .fold(ItemSet::ALL, ItemSet::intersection)
+}
+
+fn compartment_common_items(rucksack_str: &str) -> ItemSet {
+    let (fst, snd) = split_compartiments(rucksack_str);
+    common_items([fst, snd])
 }
 
 fn split_compartiments(rucksack_str: &str) -> (&str, &str) {
@@ -78,14 +127,38 @@ fn char_priority(c: char) -> usize {
     }
 }
 
+fn priority_char(priority: usize) -> char {
+    if priority <= 26 {
+        (b'a' + (priority - 1) as u8) as char
+    } else {
+        (b'A' + (priority - 27) as u8) as char
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn rucksack_priority(rucksack_str: &str) -> usize {
+        compartment_common_items(rucksack_str).priority_sum()
+    }
+
     #[test]
     fn test_rucksack_priority() {
         assert_eq!(rucksack_priority("vJrwpWtwJgWrhcsFMMfFFhFp"), 16);
         assert_eq!(rucksack_priority("jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL"), 38);
         assert_eq!(rucksack_priority("PmmdzqPrVvPwwTWBwg"), 42);
     }
+
+    #[test]
+    fn test_group_badge() {
+        let group = [
+            "vJrwpWtwJgWrhcsFMMfFFhFp",
+            "jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL",
+            "PmmdzqPrVvPwwTWBwg",
+        ];
+        let badge = common_items(group);
+        assert_eq!(badge.items().collect::<String>(), "r");
+        assert_eq!(badge.priority_sum(), 18);
+    }
 }
@@ -1,31 +1,90 @@
-use crate::utils::Reader;
+use std::{collections::BTreeMap, fmt};
 
-fn count_segments_predicate<P>(input_path: &str, predicate: P)
+use crate::utils::{Options, Reader};
+
+pub const FLAGS: &[&str] = &["--analyze"];
+
+fn count_segments_predicate<P>(input_path: &str, predicate: P, options: &Options)
 where
     P: Fn((Segment, Segment)) -> bool,
 {
     let bytes = std::fs::read(input_path).expect("failed to read input file");
     let mut reader = Reader::from_bytes(&bytes);
 
+    // pairs are only kept around when they are analyzed
+    let analyze = options.flag("--analyze");
+    let mut pairs = Vec::new();
     let mut num_true = 0;
     while reader.has_next() {
         let segments = parse_segments(&mut reader);
         num_true += predicate(segments) as usize;
+        if analyze {
+            pairs.push(segments);
+        }
     }
 
+    if analyze {
+        print_analysis(&pairs);
+    }
     println!("{}", num_true);
 }
 
-pub fn part1(input_path: &str) {
-    count_segments_predicate(input_path, |(fst, snd)| {
-        fst.is_inside(&snd) || snd.is_inside(&fst)
-    })
+pub fn part1(input_path: &str, options: &Options) {
+    count_segments_predicate(
+        input_path,
+        |(fst, snd)| fst.is_inside(&snd) || snd.is_inside(&fst),
+        options,
+    )
+}
+
+pub fn part2(input_path: &str, options: &Options) {
+    count_segments_predicate(input_path, |(fst, snd)| fst.overlaps(&snd), options)
 }
 
-pub fn part2(input_path: &str) {
-    count_segments_predicate(input_path, |(fst, snd)| fst.overlaps(&snd))
+fn print_analysis(pairs: &[(Segment, Segment)]) {
+    for (i, (fst, snd)) in pairs.iter().enumerate() {
+        let relation = match Relation::between(fst, snd) {
+            Relation::Containment => "containment",
+            Relation::Overlap => "overlap",
+            Relation::Disjoint => "disjoint",
+        };
+        println!(
+            "{}: {},{} {} ({} shared)",
+            i + 1,
+            fst,
+            snd,
+            relation,
+            fst.overlap_len(snd)
+        );
+    }
+
+    let segments: Vec<Segment> = pairs.iter().flat_map(|&(fst, snd)| [fst, snd]).collect();
+    let coverage = section_coverage(&segments);
+    let join = |runs: Vec<String>| {
+        if runs.is_empty() {
+            "none".to_string()
+        } else {
+            runs.join(" ")
+        }
+    };
+
+    let uncovered = coverage
+        .iter()
+        .filter(|&&(_, count)| count == 0)
+        .map(|(run, _)| run.to_string())
+        .collect();
+    println!("uncovered: {}", join(uncovered));
+
+    let max_count = coverage.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    let most_covered = coverage
+        .iter()
+        .filter(|&&(_, count)| count == max_count && count > 0)
+        .map(|(run, _)| run.to_string())
+        .collect();
+    println!("most covered ({} elves): {}", max_count, join(most_covered));
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Segment {
     start: usize,
     end: usize,
@@ -39,6 +98,70 @@ impl Segment {
     fn overlaps(&self, other: &Segment) -> bool {
         self.start <= other.end && self.end >= other.start
     }
+
+    /// Number of sections covered by both segments.
+    fn overlap_len(&self, other: &Segment) -> usize {
+        if self.overlaps(other) {
+            self.end.min(other.end) - self.start.max(other.start) + 1
+        } else {
+            0
+        }
+    }
+}
+
+impl fmt::Display for Segment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Relation {
+    // one segment lies entirely inside the other
+    Containment,
+    Overlap,
+    Disjoint,
+}
+
+impl Relation {
+    fn between(fst: &Segment, snd: &Segment) -> Relation {
+        if fst.is_inside(snd) || snd.is_inside(fst) {
+            Relation::Containment
+        } else if fst.overlaps(snd) {
+            Relation::Overlap
+        } else {
+            Relation::Disjoint
+        }
+    }
+}
+
+/// Splits the sections from 1 up to the last assigned one into runs covered
+/// by the same number of segments, in order.
+fn section_coverage(segments: &[Segment]) -> Vec<(Segment, usize)> {
+    // net change in coverage at the start of each section
+    let mut deltas: BTreeMap<usize, isize> = BTreeMap::new();
+    deltas.insert(1, 0);
+    for segment in segments {
+        *deltas.entry(segment.start).or_default() += 1;
+        *deltas.entry(segment.end + 1).or_default() -= 1;
+    }
+
+    let mut runs: Vec<(Segment, usize)> = Vec::new();
+    let mut count = 0;
+    let mut events = deltas.into_iter().peekable();
+    while let Some((start, delta)) = events.next() {
+        count += delta;
+        let Some(&(next_start, _)) = events.peek() else {
+            break;
+        };
+        let end = next_start - 1;
+        match runs.last_mut() {
+            // a segment starting right after another ends leaves the count unchanged
+            Some((run, run_count)) if *run_count == count as usize => run.end = end,
+            _ => runs.push((Segment { start, end }, count as usize)),
+        }
+    }
+    runs
 }
 
 fn parse_segments(reader: &mut Reader) -> (Segment, Segment) {
@@ -48,3 +171,49 @@ fn parse_segments(reader: &mut Reader) -> (Segment, Segment) {
     let d = reader.read_delimited_usize(b'\n');
     (Segment { start: a, end: b }, Segment { start: c, end: d })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seg(start: usize, end: usize) -> Segment {
+        Segment { start, end }
+    }
+
+    #[test]
+    fn test_relations() {
+        assert_eq!(
+            Relation::between(&seg(2, 4), &seg(6, 8)),
+            Relation::Disjoint
+        );
+        assert_eq!(Relation::between(&seg(5, 7), &seg(7, 9)), Relation::Overlap);
+        assert_eq!(
+            Relation::between(&seg(2, 8), &seg(3, 7)),
+            Relation::Containment
+        );
+        assert_eq!(
+            Relation::between(&seg(6, 6), &seg(4, 6)),
+            Relation::Containment
+        );
+        assert_eq!(seg(2, 4).overlap_len(&seg(6, 8)), 0);
+        assert_eq!(seg(5, 7).overlap_len(&seg(7, 9)), 1);
+        assert_eq!(seg(2, 8).overlap_len(&seg(3, 7)), 5);
+    }
+
+    #[test]
+    fn test_section_coverage() {
+        let coverage = section_coverage(&[seg(3, 4), seg(4, 6), seg(7, 7), seg(10, 10)]);
+        assert_eq!(
+            coverage,
+            vec![
+                (seg(1, 2), 0),
+                (seg(3, 3), 1),
+                (seg(4, 4), 2),
+                (seg(5, 7), 1),
+                (seg(8, 9), 0),
+                (seg(10, 10), 1),
+            ]
+        );
+        assert_eq!(section_coverage(&[]), vec![]);
+    }
+}
@@ -1,5 +1,10 @@
-use crate::utils::Reader;
+use std::fmt;
 
+use crate::utils::Options;
+
+pub const FLAGS: &[&str] = &["--crane=", "--trace"];
+
+#[derive(Clone, Copy)]
 struct Move {
     num: usize,
     from: usize,
@@ -8,70 +13,328 @@ struct Move {
 
 type Stacks = Vec<Vec<u8>>;
 
-fn run_crane<F>(input_path: &str, crane_fn: F)
+#[derive(Debug)]
+struct InputError {
+    line_num: usize,
+    message: String,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line_num, self.message)
+    }
+}
+
+fn run_crane(input_path: &str, options: &Options, default_crane: &dyn Crane) {
+    let selected_crane = options.value("--crane").map(parse_crane);
+    let crane = selected_crane.as_deref().unwrap_or(default_crane);
+
+    let input = std::fs::read_to_string(input_path).unwrap();
+    let trace = options.flag("--trace");
+
+    let mut lines = input.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut stacks = parse_drawing(&mut lines).unwrap_or_else(|err| panic!("{}", err));
+
+    if trace {
+        println!("{}", render_stacks(&stacks));
+    }
+
+    for (line_num, line) in lines {
+        let m = parse_move(line)
+            .and_then(|m| validate_move(&stacks, &m).map(|_| m))
+            .map_err(|message| InputError { line_num, message })
+            .unwrap_or_else(|err| panic!("{}", err));
+
+        // moving crates onto the stack they came from changes nothing
+        if m.from != m.to {
+            crane.apply(&mut stacks, m);
+        }
+        if trace {
+            println!("move {} from {} to {}", m.num, m.from + 1, m.to + 1);
+            println!("{}", render_stacks(&stacks));
+        }
+    }
+
+    // empty stacks have no top crate, leave a gap for them
+    let code: String = stacks
+        .iter()
+        .map(|s| s.last().map_or(' ', |&b| b as char))
+        .collect();
+    println!("{}", code);
+}
+
+/// Reads the drawing of the stacks, up to and including the empty line
+/// that follows the label line.
+/// Lines may have their trailing spaces trimmed, and labels can have
+/// multiple digits; crates are always 4 columns apart.
+fn parse_drawing<'a, I>(lines: &mut I) -> Result<Stacks, InputError>
 where
-    F: Fn(&mut Stacks, Move),
+    I: Iterator<Item = (usize, &'a str)>,
 {
-    let bytes = std::fs::read(input_path).unwrap();
+    let mut drawing_lines = Vec::new();
+    let (label_line_num, label_line) = loop {
+        let (line_num, line) = lines.next().ok_or_else(|| InputError {
+            line_num: drawing_lines.len() + 1,
+            message: "missing stack labels".to_string(),
+        })?;
+        if line.trim_start().starts_with(|c: char| c.is_ascii_digit()) {
+            break (line_num, line);
+        }
+        drawing_lines.push((line_num, line.trim_end().as_bytes()));
+    };
 
-    let line_length = 1 + bytes.iter().position(|&b| b == b'\n').unwrap();
-    let num_stacks = line_length / 4;
+    let labels: Vec<&str> = label_line.split_whitespace().collect();
+    for (i, label) in labels.iter().enumerate() {
+        if label.parse::<usize>() != Ok(i + 1) {
+            return Err(InputError {
+                line_num: label_line_num,
+                message: format!("expected stack label {}, found {:?}", i + 1, label),
+            });
+        }
+    }
+    let num_stacks = labels.len();
 
     let mut stacks = vec![Vec::new(); num_stacks];
-
-    let mut line_start = 0;
-    while bytes[line_start + 1] != b'1' {
-        // read a line
-        for i in 0..num_stacks {
-            let chr = bytes[line_start + 4 * i + 1];
-            if chr != b' ' {
-                stacks[i].push(chr);
+    // read bottom to top
+    for (level, &(line_num, line)) in drawing_lines.iter().rev().enumerate() {
+        let error = |message: String| InputError { line_num, message };
+        if line.len() > 4 * num_stacks - 1 {
+            return Err(error(format!("line is wider than {} stacks", num_stacks)));
+        }
+        for (i, stack) in stacks.iter_mut().enumerate() {
+            let cell = &line[line.len().min(4 * i)..line.len().min(4 * i + 3)];
+            match cell {
+                b"" | b"   " => {}
+                [b'[', chr, b']'] if stack.len() == level => stack.push(*chr),
+                [b'[', _, b']'] => {
+                    return Err(error(format!("crate floating above stack {}", i + 1)));
+                }
+                _ => {
+                    let cell = String::from_utf8_lossy(cell);
+                    return Err(error(format!("invalid crate {:?}", cell)));
+                }
             }
         }
+    }
 
-        line_start += line_length;
+    match lines.next() {
+        Some((_, "")) | None => Ok(stacks),
+        Some((line_num, _)) => Err(InputError {
+            line_num,
+            message: "expected an empty line after the stack labels".to_string(),
+        }),
     }
-    // we are at the label line; skip it
-    let mut pos = line_start + line_length;
-    pos += 1; // skip empty line
+}
 
-    for stack in stacks.iter_mut() {
-        stack.reverse();
+fn parse_move(line: &str) -> Result<Move, String> {
+    let invalid = || format!("invalid move {:?}", line);
+    let words: Vec<&str> = line.split(' ').collect();
+    let (num, from, to) = match words[..] {
+        ["move", num, "from", from, "to", to] => (num, from, to),
+        _ => return Err(invalid()),
+    };
+    let parse = |num: &str| num.parse::<usize>().map_err(|_| invalid());
+    let (num, from, to) = (parse(num)?, parse(from)?, parse(to)?);
+    if from == 0 || to == 0 {
+        return Err("stacks are numbered from 1".to_string());
     }
+    Ok(Move {
+        num,
+        from: from - 1,
+        to: to - 1,
+    })
+}
 
-    let mut reader = Reader::from_bytes(&bytes[pos..]);
-    while reader.has_next() {
-        reader.skip_lit(b"move ");
-        let num = reader.read_delimited_usize(b' ');
-        reader.skip_lit(b"from ");
-        let from = reader.read_delimited_usize(b' ') - 1;
-        reader.skip_lit(b"to ");
-        let to = reader.read_delimited_usize(b'\n') - 1;
+fn validate_move(stacks: &Stacks, m: &Move) -> Result<(), String> {
+    for stack_num in [m.from, m.to] {
+        if stack_num >= stacks.len() {
+            return Err(format!(
+                "there is no stack {}, only {}",
+                stack_num + 1,
+                stacks.len()
+            ));
+        }
+    }
+    let available = stacks[m.from].len();
+    if m.num > available {
+        return Err(format!(
+            "cannot move {} crates from stack {}, it only holds {}",
+            m.num,
+            m.from + 1,
+            available
+        ));
+    }
+    Ok(())
+}
 
-        crane_fn(&mut stacks, Move { num, from, to });
+/// Draws the stacks in the same format as the puzzle input,
+/// including the label line.
+fn render_stacks(stacks: &Stacks) -> String {
+    let height = stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+    let mut lines = Vec::with_capacity(height + 1);
+    for level in (0..height).rev() {
+        let cells: Vec<String> = stacks
+            .iter()
+            .map(|stack| match stack.get(level) {
+                Some(&chr) => format!("[{}]", chr as char),
+                None => "   ".to_string(),
+            })
+            .collect();
+        lines.push(cells.join(" "));
     }
+    let labels: Vec<String> = (1..=stacks.len()).map(|n| format!("{:^3}", n)).collect();
+    lines.push(labels.join(" "));
 
-    let code_vec: Vec<u8> = stacks.iter().map(|s| s[s.len() - 1]).collect();
-    let code = std::str::from_utf8(&code_vec).unwrap();
-    println!("{}", code);
+    let mut drawing = lines.join("\n");
+    drawing.push('\n');
+    drawing
+}
+
+pub fn part1(input_path: &str, options: &Options) {
+    run_crane(input_path, options, &CrateMover9000)
 }
 
-pub fn part1(input_path: &str) {
-    run_crane(input_path, |stacks, Move { num, from, to }| {
+pub fn part2(input_path: &str, options: &Options) {
+    run_crane(input_path, options, &CrateMover9001)
+}
+
+trait Crane {
+    /// Executes a move; the move is known to be valid and to
+    /// involve two different stacks.
+    fn apply(&self, stacks: &mut Stacks, m: Move);
+}
+
+/// Moves crates one at a time
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn apply(&self, stacks: &mut Stacks, Move { num, from, to }: Move) {
         for _ in 0..num {
             let b = stacks[from].pop().unwrap();
             stacks[to].push(b);
         }
-    })
+    }
 }
 
-pub fn part2(input_path: &str) {
-    run_crane(input_path, |stacks, Move { num, from, to }| {
+/// Moves all crates at once, keeping their order
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn apply(&self, stacks: &mut Stacks, Move { num, from, to }: Move) {
         let new_from_len = stacks[from].len() - num;
         for i in 0..num {
             let b = stacks[from][new_from_len + i];
             stacks[to].push(b);
         }
         stacks[from].truncate(new_from_len);
-    })
+    }
+}
+
+/// Can carry a limited number of crates per trip, so it moves them in
+/// batches from the top, keeping the order within each batch.
+/// With a capacity of one, this behaves like the CrateMover 9000.
+struct BatchCrane {
+    capacity: usize,
+}
+
+impl Crane for BatchCrane {
+    fn apply(&self, stacks: &mut Stacks, Move { num, from, to }: Move) {
+        let mut remaining = num;
+        while remaining > 0 {
+            let batch_size = remaining.min(self.capacity);
+            let new_from_len = stacks[from].len() - batch_size;
+            let batch = stacks[from].split_off(new_from_len);
+            stacks[to].extend(batch);
+            remaining -= batch_size;
+        }
+    }
+}
+
+// `9000`, `9001` or `batch:<capacity>`
+fn parse_crane(name: &str) -> Box<dyn Crane> {
+    match name {
+        "9000" => Box::new(CrateMover9000),
+        "9001" => Box::new(CrateMover9001),
+        _ => {
+            let capacity = name
+                .strip_prefix("batch:")
+                .and_then(|capacity| capacity.parse().ok())
+                .filter(|&capacity| capacity > 0)
+                .unwrap_or_else(|| panic!("unknown crane {:?}", name));
+            Box::new(BatchCrane { capacity })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DRAWING: &str = "    [D]    \n[N] [C]    \n[Z] [M] [P]\n 1   2   3 \n";
+
+    fn parse(drawing: &str) -> Result<Stacks, InputError> {
+        let mut lines = drawing.lines().enumerate().map(|(i, line)| (i + 1, line));
+        parse_drawing(&mut lines)
+    }
+
+    #[test]
+    fn test_drawing_round_trip() {
+        let stacks = parse(DRAWING).unwrap();
+        assert_eq!(stacks, vec![b"ZN".to_vec(), b"MCD".to_vec(), b"P".to_vec()]);
+        assert_eq!(render_stacks(&stacks), DRAWING);
+    }
+
+    #[test]
+    fn test_ragged_drawing() {
+        let stacks = parse("    [D]\n[N] [C]\n[Z] [M] [P]\n 1   2   3\n").unwrap();
+        assert_eq!(stacks, vec![b"ZN".to_vec(), b"MCD".to_vec(), b"P".to_vec()]);
+    }
+
+    #[test]
+    fn test_many_stacks() {
+        let drawing = concat!(
+            "                                        [K]\n",
+            "[A] [B] [C] [D] [E] [F] [G] [H] [I] [J] [L]\n",
+            " 1   2   3   4   5   6   7   8   9  10  11 \n",
+        );
+        let stacks = parse(drawing).unwrap();
+        assert_eq!(stacks.len(), 11);
+        assert_eq!(stacks[10], b"LK".to_vec());
+        assert_eq!(render_stacks(&stacks), drawing);
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert_eq!(parse("    [D]\n[N]\n 1   2\n").unwrap_err().line_num, 1);
+        assert_eq!(parse("[N] [C]\n 1   3\n").unwrap_err().line_num, 2);
+
+        let stacks = parse(DRAWING).unwrap();
+        let check = |line| parse_move(line).and_then(|m| validate_move(&stacks, &m));
+        assert!(check("move 2 from 1 to 3").is_ok());
+        assert!(check("move 3 from 1 to 3").is_err());
+        assert!(check("move 1 from 4 to 3").is_err());
+        assert!(check("move 1 from 0 to 3").is_err());
+        assert!(check("move one from 1 to 3").is_err());
+    }
+
+    #[test]
+    fn test_cranes() {
+        let run = |crane: &dyn Crane| {
+            let mut stacks = vec![b"ABCDE".to_vec(), Vec::new()];
+            crane.apply(
+                &mut stacks,
+                Move {
+                    num: 5,
+                    from: 0,
+                    to: 1,
+                },
+            );
+            String::from_utf8(stacks[1].clone()).unwrap()
+        };
+        assert_eq!(run(&CrateMover9000), "EDCBA");
+        assert_eq!(run(&CrateMover9001), "ABCDE");
+        assert_eq!(run(&BatchCrane { capacity: 1 }), "EDCBA");
+        assert_eq!(run(&BatchCrane { capacity: 2 }), "DEBCA");
+        assert_eq!(run(&BatchCrane { capacity: 5 }), "ABCDE");
+    }
 }
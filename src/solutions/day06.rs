@@ -1,29 +1,200 @@
-fn find_marker(input_path: &str, n: usize) {
-    let bytes = std::fs::read(input_path).unwrap();
-
-    let mut pos = 0;
-    'outer: while pos + n < bytes.len() {
-        for i in (pos..pos + n).rev() {
-            for j in (pos..i).rev() {
-                if bytes[i] == bytes[j] {
-                    // j can never be part of the marker
-                    pos = j + 1;
-                    continue 'outer;
-                }
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    hash::Hash,
+    io::{self, BufReader, Read, Write},
+};
+
+use crate::utils::Options;
+
+pub const FLAGS: &[&str] = &["--all", "--chars", "--window="];
+
+fn solve(input_path: &str, n: usize, options: &Options) {
+    let n = options.parse_or("--window", n);
+
+    // the datastream is read incrementally so arbitrarily long signals can be
+    // processed, "-" reads it from stdin
+    let input: Box<dyn Read> = if input_path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(input_path).expect("could not open file"))
+    };
+    let bytes = BufReader::new(input)
+        .bytes()
+        .map(|byte| byte.expect("could not read datastream"));
+
+    // by default symbols are bytes, --chars switches to unicode characters
+    let mut markers: Box<dyn Iterator<Item = usize>> = if options.flag("--chars") {
+        let chars = Utf8Chars { bytes }.take_while(|&c| c != '\n');
+        Box::new(find_markers(chars, n))
+    } else {
+        Box::new(find_markers(bytes.take_while(|&b| b != b'\n'), n))
+    };
+
+    // stdout is line buffered, so each marker shows up as soon as it is found
+    let mut out = io::stdout().lock();
+    if options.flag("--all") {
+        for pos in markers {
+            writeln!(out, "{}", pos).unwrap();
+        }
+    } else {
+        match markers.next() {
+            Some(pos) => writeln!(out, "{}", pos).unwrap(),
+            None => writeln!(out, "No marker found").unwrap(),
+        }
+    }
+}
+
+pub fn part1(input_path: &str, options: &Options) {
+    solve(input_path, 4, options);
+}
+
+pub fn part2(input_path: &str, options: &Options) {
+    solve(input_path, 14, options);
+}
+
+/// Yields the number of symbols processed at every point where the last
+/// `n` symbols were all different.
+fn find_markers<T, I>(symbols: I, n: usize) -> impl Iterator<Item = usize>
+where
+    T: Eq + Hash + Clone,
+    I: IntoIterator<Item = T>,
+{
+    let mut detector = MarkerDetector::new(n);
+    symbols
+        .into_iter()
+        .filter_map(move |symbol| detector.push(symbol))
+}
+
+struct MarkerDetector<T> {
+    window_size: usize,
+    // the last window_size symbols, oldest first
+    window: VecDeque<T>,
+    // the position at which each symbol in the window was last seen
+    last_seen: HashMap<T, usize>,
+    // number of symbols processed so far
+    pos: usize,
+    // start of the longest run of distinct symbols ending at pos
+    run_start: usize,
+}
+
+impl<T: Eq + Hash + Clone> MarkerDetector<T> {
+    fn new(window_size: usize) -> Self {
+        MarkerDetector {
+            window_size,
+            window: VecDeque::with_capacity(window_size),
+            last_seen: HashMap::new(),
+            pos: 0,
+            run_start: 0,
+        }
+    }
+
+    /// Processes the next symbol, returning the marker position when the
+    /// window ending at this symbol consists of distinct symbols.
+    fn push(&mut self, symbol: T) -> Option<usize> {
+        if self.window_size == 0 {
+            self.pos += 1;
+            return Some(self.pos);
+        }
+
+        // forget the symbol leaving the window unless it has been seen since,
+        // keeping memory bounded by the window size rather than the alphabet
+        if self.window.len() == self.window_size {
+            let old = self.window.pop_front().unwrap();
+            let old_pos = self.pos - self.window_size;
+            if self.last_seen.get(&old) == Some(&old_pos) {
+                self.last_seen.remove(&old);
             }
         }
-        // checks passed; this is marker
-        println!("{}", pos + n);
-        return;
+
+        if let Some(prev) = self.last_seen.insert(symbol.clone(), self.pos) {
+            // the run can not include both occurrences
+            self.run_start = self.run_start.max(prev + 1);
+        }
+        self.window.push_back(symbol);
+        self.pos += 1;
+
+        if self.pos - self.run_start >= self.window_size {
+            Some(self.pos)
+        } else {
+            None
+        }
     }
+}
 
-    println!("No marker found");
+/// Decodes utf-8 characters from a byte stream without buffering it.
+struct Utf8Chars<I> {
+    bytes: I,
 }
 
-pub fn part1(input_path: &str) {
-    find_marker(input_path, 4);
+impl<I: Iterator<Item = u8>> Iterator for Utf8Chars<I> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let lead = self.bytes.next()?;
+        let len = match lead.leading_ones() {
+            0 => 1,
+            n @ 2..=4 => n as usize,
+            _ => panic!("invalid utf-8 lead byte {:#04x}", lead),
+        };
+        let mut buf = [lead, 0, 0, 0];
+        for byte in &mut buf[1..len] {
+            *byte = self.bytes.next().expect("truncated utf-8 character");
+        }
+        let c = std::str::from_utf8(&buf[..len]).expect("invalid utf-8 character");
+        c.chars().next()
+    }
 }
 
-pub fn part2(input_path: &str) {
-    find_marker(input_path, 14);
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_marker(datastream: &str, n: usize) -> Option<usize> {
+        find_markers(datastream.bytes(), n).next()
+    }
+
+    #[test]
+    fn test_first_marker() {
+        let examples = [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ];
+        for (datastream, packet, message) in examples {
+            assert_eq!(first_marker(datastream, 4), Some(packet));
+            assert_eq!(first_marker(datastream, 14), Some(message));
+        }
+        assert_eq!(first_marker("aaaa", 2), None);
+    }
+
+    #[test]
+    fn test_all_markers() {
+        let markers: Vec<usize> = find_markers("abcab".chars(), 3).collect();
+        assert_eq!(markers, vec![3, 4, 5]);
+        let markers: Vec<usize> = find_markers("αβαγδ".chars(), 3).collect();
+        assert_eq!(markers, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_bounded_memory() {
+        let mut detector = MarkerDetector::new(4);
+        for symbol in 0..10_000u32 {
+            detector.push(symbol % 1000);
+            assert!(detector.last_seen.len() <= 4);
+        }
+        assert_eq!(detector.pos, 10_000);
+    }
+
+    #[test]
+    fn test_utf8_chars() {
+        let text = "aé€😀";
+        let chars: String = Utf8Chars {
+            bytes: text.bytes(),
+        }
+        .collect();
+        assert_eq!(chars, text);
+    }
 }
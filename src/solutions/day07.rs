@@ -1,107 +1,446 @@
-use std::collections::HashMap;
+use std::{cmp::Reverse, collections::BTreeMap, fmt};
 
-use crate::utils::Reader;
+use crate::utils::Options;
 
-pub fn part1(input_path: &str) {
-    let directories = parse_directories(input_path);
-    let total_size: usize = directories
+pub const FLAGS: &[&str] = &[
+    "--disk-size=",
+    "--du",
+    "--plan",
+    "--required=",
+    "--threshold=",
+    "--tree",
+];
+
+pub fn part1(input_path: &str, options: &Options) {
+    let fs = read_filesystem(input_path, options);
+    let threshold = options.parse_or("--threshold", 100000);
+    let total_size: usize = fs
+        .directories
         .iter()
         .map(|d| d.total_size)
-        .filter(|&s| s <= 100000)
+        .filter(|&s| s <= threshold)
         .sum();
     println!("{}", total_size);
 }
 
-pub fn part2(input_path: &str) {
-    let directories = parse_directories(input_path);
-    let disk_size = 70000000;
-    let used = directories[0].total_size;
-    let needed = 30000000usize.saturating_sub(disk_size - used);
-    let size: usize = directories
+pub fn part2(input_path: &str, options: &Options) {
+    let fs = read_filesystem(input_path, options);
+    let disk_size: usize = options.parse_or("--disk-size", 70000000);
+    let required: usize = options.parse_or("--required", 30000000);
+    let used = fs.directories[ROOT].total_size;
+    let needed = required.saturating_sub(disk_size.saturating_sub(used));
+
+    if options.flag("--plan") {
+        match fs.plan_deletion(needed) {
+            Some(dirs) => {
+                for &dir in &dirs {
+                    println!("{}\t{}", fs.directories[dir].total_size, fs.path(dir));
+                }
+                let freed: usize = dirs.iter().map(|&d| fs.directories[d].total_size).sum();
+                println!("{}", freed);
+            }
+            None => println!("cannot free {} bytes", needed),
+        }
+        return;
+    }
+
+    let size = fs
+        .directories
         .iter()
         .map(|d| d.total_size)
         .filter(|&s| s >= needed)
-        .min()
-        .unwrap();
-    println!("{}", size);
+        .min();
+    match size {
+        Some(size) => println!("{}", size),
+        None => println!("cannot free {} bytes", needed),
+    }
+}
+
+fn read_filesystem(input_path: &str, options: &Options) -> FileSystem {
+    let bytes = std::fs::read(input_path).unwrap();
+    let fs = parse_filesystem(&bytes).unwrap_or_else(|err| panic!("{}", err));
+    if options.flag("--tree") {
+        print!("{}", fs.tree_report());
+    }
+    if options.flag("--du") {
+        print!("{}", fs.du_report());
+    }
+    fs
+}
+
+const ROOT: usize = 0;
+
+struct FileSystem {
+    // directories are stored in creation order, so a directory
+    // always comes after its parent
+    directories: Vec<Directory>,
 }
 
 struct Directory {
-    total_size: usize,
+    name: String,
+    // the root directory is its own parent
     parent_index: usize,
+    subdirectories: BTreeMap<String, usize>,
+    // file name to size
+    files: BTreeMap<String, usize>,
+    // size of all files in this directory and its subdirectories
+    total_size: usize,
 }
 
-fn parse_directories(input_path: &str) -> Vec<Directory> {
-    let bytes = std::fs::read(input_path).unwrap();
-    let mut reader = Reader::from_bytes(&bytes);
+impl FileSystem {
+    fn path(&self, dir_index: usize) -> String {
+        if dir_index == ROOT {
+            return "/".to_string();
+        }
+        let mut components = Vec::new();
+        let mut current = dir_index;
+        while current != ROOT {
+            components.push(self.directories[current].name.as_str());
+            current = self.directories[current].parent_index;
+        }
+        components
+            .iter()
+            .rev()
+            .map(|name| format!("/{}", name))
+            .collect()
+    }
 
-    let mut directory_index: HashMap<(usize, &[u8]), usize> = HashMap::new();
+    /// Cumulative size and path of every directory, children before their
+    /// parents, like `du`.
+    fn du_report(&self) -> String {
+        let mut report = String::new();
+        self.write_du(ROOT, &mut report);
+        report
+    }
 
-    // this should always be the first command
-    reader.skip_lit(b"$ cd /\n");
-    // start with only the root directory
-    let mut directories = vec![Directory {
+    fn write_du(&self, dir_index: usize, out: &mut String) {
+        for &child in self.directories[dir_index].subdirectories.values() {
+            self.write_du(child, out);
+        }
+        let size = self.directories[dir_index].total_size;
+        out.push_str(&format!("{}\t{}\n", size, self.path(dir_index)));
+    }
+
+    /// Finds the fewest directories to delete that together free at least
+    /// `needed` bytes. The root directory is never deleted, so this may take
+    /// several directories. When a single directory is enough the smallest
+    /// one is picked, otherwise the largest top-level directories are.
+    fn plan_deletion(&self, needed: usize) -> Option<Vec<usize>> {
+        if needed == 0 {
+            return Some(Vec::new());
+        }
+        let smallest_sufficient = (0..self.directories.len())
+            .filter(|&dir| dir != ROOT && self.directories[dir].total_size >= needed)
+            .min_by_key(|&dir| self.directories[dir].total_size);
+        if let Some(dir) = smallest_sufficient {
+            return Some(vec![dir]);
+        }
+
+        // deleting a directory frees at least as much as deleting anything
+        // inside it, so the largest top-level directories go first
+        let mut candidates: Vec<usize> = self.directories[ROOT]
+            .subdirectories
+            .values()
+            .copied()
+            .collect();
+        candidates.sort_by_key(|&dir| Reverse(self.directories[dir].total_size));
+        let mut freed = 0;
+        let mut dirs = Vec::new();
+        for dir in candidates {
+            if freed >= needed {
+                break;
+            }
+            freed += self.directories[dir].total_size;
+            dirs.push(dir);
+        }
+        dirs.sort_unstable();
+        (freed >= needed).then_some(dirs)
+    }
+
+    /// Renders the filesystem in the format used by the puzzle text,
+    /// with the cumulative size added to each directory.
+    fn tree_report(&self) -> String {
+        let mut report = String::new();
+        self.write_tree(ROOT, 0, &mut report);
+        report
+    }
+
+    fn write_tree(&self, dir_index: usize, depth: usize, out: &mut String) {
+        let dir = &self.directories[dir_index];
+        let name = if dir_index == ROOT { "/" } else { &dir.name };
+        let indent = "  ".repeat(depth);
+        out.push_str(&format!(
+            "{}- {} (dir, size={})\n",
+            indent, name, dir.total_size
+        ));
+
+        // merge subdirectories and files, sorted by name
+        let mut entries: Vec<(&str, Option<usize>, usize)> = dir
+            .subdirectories
+            .iter()
+            .map(|(name, &index)| (name.as_str(), Some(index), 0))
+            .chain(
+                dir.files
+                    .iter()
+                    .map(|(name, &size)| (name.as_str(), None, size)),
+            )
+            .collect();
+        entries.sort_by_key(|&(name, _, _)| name);
+
+        for (name, subdirectory, size) in entries {
+            match subdirectory {
+                Some(child) => self.write_tree(child, depth + 1, out),
+                None => out.push_str(&format!("{}  - {} (file, size={})\n", indent, name, size)),
+            }
+        }
+    }
+}
+
+fn new_directory(name: &str, parent_index: usize) -> Directory {
+    Directory {
+        name: name.to_string(),
+        parent_index,
+        subdirectories: BTreeMap::new(),
+        files: BTreeMap::new(),
         total_size: 0,
-        parent_index: 0,
-    }];
-    let mut current_directory = 0;
-
-    while reader.has_next() {
-        reader.skip_lit(b"$ ");
-        if reader.peek() == b'c' {
-            reader.skip_lit(b"cd ");
-            match reader.peek() {
-                b'/' => {
-                    reader.skip_lit(b"/\n");
-                    current_directory = 0;
-                }
-                b'.' => {
-                    reader.skip_lit(b"..\n");
-                    current_directory = directories[current_directory].parent_index;
-                }
-                _ => {
-                    let dir_name = reader.take_while(|b| b != b'\n');
-                    reader.skip_lit(b"\n");
-
-                    // check if dir already exists
-                    if let Some(&dir_id) = directory_index.get(&(current_directory, dir_name)) {
-                        current_directory = dir_id;
-                    } else {
-                        // create a new directory
-                        let dir_id = directories.len();
-                        let dir = Directory {
-                            total_size: 0,
-                            parent_index: current_directory,
-                        };
-                        directory_index.insert((current_directory, dir_name), dir_id);
-                        directories.push(dir);
-                        current_directory = dir_id;
-                    }
-                }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct TranscriptError {
+    line_num: usize,
+    message: String,
+}
+
+impl fmt::Display for TranscriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line_num, self.message)
+    }
+}
+
+fn parse_filesystem(bytes: &[u8]) -> Result<FileSystem, TranscriptError> {
+    let mut builder = FileSystemBuilder {
+        // start with only the root directory
+        directories: vec![new_directory("", ROOT)],
+        current_directory: ROOT,
+    };
+    // whether the lines that follow are output of an ls command
+    let mut listing = false;
+
+    let transcript = String::from_utf8_lossy(bytes);
+    for (line_num, line) in transcript.lines().enumerate() {
+        let error = |message: String| TranscriptError {
+            line_num: line_num + 1,
+            message,
+        };
+
+        if let Some(command) = line.strip_prefix("$ ") {
+            listing = false;
+            if let Some(path) = command.strip_prefix("cd ") {
+                builder.change_directory(path).map_err(error)?;
+            } else if command == "ls" {
+                listing = true;
+            } else {
+                return Err(error(format!("unknown command {:?}", command)));
             }
+        } else if !listing {
+            return Err(error("output outside of an ls command".to_string()));
+        } else if let Some(name) = line.strip_prefix("dir ") {
+            let current = builder.current_directory;
+            builder.subdirectory(current, name).map_err(error)?;
         } else {
-            reader.skip_lit(b"ls\n");
-            // read entries
-            while reader.has_next() && reader.peek() != b'$' {
-                if reader.peek() == b'd' {
-                    reader.skip_lit(b"dir ");
-                    reader.skip_while(|b| b != b'\n');
-                    reader.skip_lit(b"\n");
-                } else {
-                    let size = reader.read_usize();
-                    directories[current_directory].total_size += size;
-                    reader.skip_while(|b| b != b'\n');
-                    reader.skip_lit(b"\n");
-                }
+            let (size, name) = line
+                .split_once(' ')
+                .and_then(|(size, name)| Some((size.parse().ok()?, name)))
+                .ok_or_else(|| error(format!("invalid ls entry {:?}", line)))?;
+            builder.add_file(name, size).map_err(error)?;
+        }
+    }
+
+    Ok(builder.finish())
+}
+
+struct FileSystemBuilder {
+    directories: Vec<Directory>,
+    current_directory: usize,
+}
+
+impl FileSystemBuilder {
+    /// Follows a (possibly multi-component) path, creating directories we
+    /// have not seen yet along the way.
+    fn change_directory(&mut self, path: &str) -> Result<(), String> {
+        let mut dir_index = if path.starts_with('/') {
+            ROOT
+        } else {
+            self.current_directory
+        };
+        for component in path.split('/') {
+            dir_index = match component {
+                "" | "." => dir_index,
+                // like a shell, cd .. in the root directory is a no-op
+                ".." => self.directories[dir_index].parent_index,
+                name => self.subdirectory(dir_index, name)?,
+            };
+        }
+        self.current_directory = dir_index;
+        Ok(())
+    }
+
+    fn subdirectory(&mut self, parent_index: usize, name: &str) -> Result<usize, String> {
+        let parent = &self.directories[parent_index];
+        if let Some(&dir_index) = parent.subdirectories.get(name) {
+            return Ok(dir_index);
+        }
+        if parent.files.contains_key(name) {
+            return Err(format!("{:?} is a file, not a directory", name));
+        }
+
+        let dir_index = self.directories.len();
+        self.directories.push(new_directory(name, parent_index));
+        self.directories[parent_index]
+            .subdirectories
+            .insert(name.to_string(), dir_index);
+        Ok(dir_index)
+    }
+
+    // files may be listed more than once, but should not change in between
+    fn add_file(&mut self, name: &str, size: usize) -> Result<(), String> {
+        let dir = &mut self.directories[self.current_directory];
+        if dir.subdirectories.contains_key(name) {
+            return Err(format!("{:?} is a directory, not a file", name));
+        }
+        match dir.files.insert(name.to_string(), size) {
+            Some(prev_size) if prev_size != size => Err(format!(
+                "file {:?} was listed with size {} before, now {}",
+                name, prev_size, size
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn finish(mut self) -> FileSystem {
+        for i in (0..self.directories.len()).rev() {
+            let file_size: usize = self.directories[i].files.values().sum();
+            self.directories[i].total_size += file_size;
+            if i != ROOT {
+                let size = self.directories[i].total_size;
+                let parent_index = self.directories[i].parent_index;
+                self.directories[parent_index].total_size += size;
             }
         }
+
+        FileSystem {
+            directories: self.directories,
+        }
     }
+}
 
-    for i in (1..directories.len()).rev() {
-        let size = directories[i].total_size;
-        let parent_index = directories[i].parent_index;
-        directories[parent_index].total_size += size;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[u8] = b"\
+$ cd /
+$ ls
+dir a
+14848514 b.txt
+8504156 c.dat
+dir d
+$ cd a
+$ ls
+dir e
+29116 f
+2557 g
+62596 h.lst
+$ cd e
+$ ls
+584 i
+$ cd ..
+$ cd ..
+$ cd d
+$ ls
+4060174 j
+8033020 d.log
+5626152 d.ext
+7214296 k
+";
+
+    #[test]
+    fn test_du_report() {
+        let fs = parse_filesystem(EXAMPLE).unwrap();
+        assert_eq!(
+            fs.du_report(),
+            "584\t/a/e\n94853\t/a\n24933642\t/d\n48381165\t/\n"
+        );
     }
 
-    directories
+    #[test]
+    fn test_plan_deletion() {
+        let fs = parse_filesystem(EXAMPLE).unwrap();
+        let paths = |needed| {
+            fs.plan_deletion(needed)
+                .map(|dirs| dirs.iter().map(|&d| fs.path(d)).collect::<Vec<_>>())
+        };
+        assert_eq!(paths(8381165), Some(vec!["/d".to_string()]));
+        assert_eq!(paths(50000), Some(vec!["/a".to_string()]));
+        assert_eq!(
+            paths(25000000),
+            Some(vec!["/a".to_string(), "/d".to_string()])
+        );
+        assert_eq!(paths(30000000), None);
+    }
+
+    #[test]
+    fn test_plan_deletion_many_dirs() {
+        let mut transcript = String::from("$ cd /\n$ ls\n");
+        for i in 0..120 {
+            transcript.push_str(&format!("dir d{}\n", i));
+        }
+        for i in 0..120 {
+            transcript.push_str(&format!("$ cd /d{}\n$ ls\n{} f\n", i, 200000 + i));
+        }
+        let fs = parse_filesystem(transcript.as_bytes()).unwrap();
+
+        // the ten largest directories are needed
+        let dirs = fs.plan_deletion(2000000).unwrap();
+        assert_eq!(dirs.len(), 10);
+        let freed: usize = dirs.iter().map(|&d| fs.directories[d].total_size).sum();
+        assert_eq!(freed, (110..120).map(|i| 200000 + i).sum::<usize>());
+        assert_eq!(fs.plan_deletion(fs.directories[ROOT].total_size + 1), None);
+    }
+
+    #[test]
+    fn test_repeated_ls_and_unlisted_dirs() {
+        let transcript = b"\
+$ ls
+10 a.txt
+$ cd x/y
+$ ls
+5 b
+$ cd ../..
+$ ls
+10 a.txt
+dir z
+$ cd /x
+$ ls
+3 c
+";
+        let fs = parse_filesystem(transcript).unwrap();
+        assert_eq!(fs.du_report(), "5\t/x/y\n8\t/x\n0\t/z\n18\t/\n");
+    }
+
+    fn error_line(transcript: &[u8]) -> Option<usize> {
+        parse_filesystem(transcript).err().map(|err| err.line_num)
+    }
+
+    #[test]
+    fn test_inconsistent_transcripts() {
+        // file changed size
+        assert_eq!(error_line(b"$ ls\n10 a\n$ ls\n11 a\n"), Some(4));
+        // cd into a file
+        assert_eq!(error_line(b"$ ls\n10 a\n$ cd a\n"), Some(3));
+        // output without ls
+        assert_eq!(error_line(b"$ cd /\n10 a\n"), Some(2));
+        assert_eq!(error_line(b"$ rm -rf /\n"), Some(1));
+    }
 }
@@ -1,35 +1,47 @@
-use crate::utils::{GridCoords, VecGrid};
-use std::ops::Index;
+use crate::utils::{Options, VecGrid};
 
-pub fn part1(input_path: &str) {
+pub const FLAGS: &[&str] = &["--ppm=", "--render"];
+
+pub fn part1(input_path: &str, options: &Options) {
     let bytes = std::fs::read(input_path).unwrap();
-    let grid = AsciiGrid::from_bytes(bytes);
-    let mut is_visible = VecGrid::full(grid.width, grid.height, false);
+    let grid = parse_heights(&bytes);
+    let is_visible = calc_visibility(&grid);
+
+    if options.flag("--render") {
+        print!("{}", is_visible.render(|&v| if v { '#' } else { '.' }));
+    }
+    if let Some(path) = options.value("--ppm") {
+        let image = is_visible.to_ppm(|&v| if v { [34, 139, 34] } else { [0, 0, 0] });
+        std::fs::write(path, image).expect("could not write image");
+    }
+
+    let num_visible: usize = is_visible.iter().filter(|&&v| v).count();
+
+    println!("{}", num_visible);
+}
+
+fn calc_visibility(grid: &VecGrid<u8>) -> VecGrid<bool> {
+    let (width, height) = (grid.width(), grid.height());
+    let mut is_visible = VecGrid::full(width, height, false);
 
     // north
-    mark_visible(&grid, &mut is_visible, grid.width, grid.height, |w, d| {
-        (w, d)
-    });
+    mark_visible(grid, &mut is_visible, width, height, |w, d| (w, d));
     // south
-    mark_visible(&grid, &mut is_visible, grid.width, grid.height, |w, d| {
-        (w, grid.height - 1 - d)
-    });
-    // east
-    mark_visible(&grid, &mut is_visible, grid.width, grid.height, |w, d| {
-        (d, w)
+    mark_visible(grid, &mut is_visible, width, height, |w, d| {
+        (w, height - 1 - d)
     });
     // west
-    mark_visible(&grid, &mut is_visible, grid.width, grid.height, |w, d| {
-        (grid.width - 1 - d, w)
+    mark_visible(grid, &mut is_visible, height, width, |w, d| (d, w));
+    // east
+    mark_visible(grid, &mut is_visible, height, width, |w, d| {
+        (width - 1 - d, w)
     });
 
-    let num_visible: usize = is_visible.iter().filter(|&&v| v).count();
-
-    println!("{}", num_visible);
+    is_visible
 }
 
 fn mark_visible<F>(
-    grid: &AsciiGrid,
+    grid: &VecGrid<u8>,
     visible: &mut VecGrid<bool>,
     width: usize,
     depth: usize,
@@ -38,104 +50,126 @@ fn mark_visible<F>(
     F: Fn(usize, usize) -> (usize, usize),
 {
     for w in 0..width {
-        let mut current_height = 0;
+        // the tallest tree seen so far; nothing blocks the view of the
+        // first tree on the line, whatever its height
+        let mut tallest: Option<u8> = None;
         for d in 0..depth {
             let coord = coord_fn(w, d);
             let height = grid[coord];
-            if height > current_height {
-                // println!("marked visible: {:?} (height {})", coord, grid[coord]);
-                current_height = height;
+            if tallest.is_none_or(|tallest| height > tallest) {
+                tallest = Some(height);
                 visible[coord] = true;
             }
         }
     }
 }
 
-pub fn part2(input_path: &str) {
+pub fn part2(input_path: &str, options: &Options) {
     let bytes = std::fs::read(input_path).unwrap();
-    let grid = AsciiGrid::from_bytes(bytes);
+    let grid = parse_heights(&bytes);
+    let scenic_scores = calc_scenic_scores(&grid);
+    let best_score: usize = scenic_scores.iter().copied().max().unwrap();
 
-    let best_score: usize = (0..grid.width)
-        .flat_map(|x| (0..grid.height).map(move |y| (x, y)))
-        .map(|(x, y)| tree_scenic_score(&grid, x, y))
-        .max()
-        .unwrap();
+    if options.flag("--render") {
+        print!(
+            "{}",
+            scenic_scores.render(|&score| {
+                let ramp = HEAT_MAP_RAMP.as_bytes();
+                let level = heat_level(score, best_score);
+                ramp[(level * (ramp.len() - 1) as f64).round() as usize] as char
+            })
+        );
+    }
+    if let Some(path) = options.value("--ppm") {
+        let image = scenic_scores.to_ppm(|&score| heat_color(heat_level(score, best_score)));
+        std::fs::write(path, image).expect("could not write image");
+    }
 
     println!("{}", best_score);
 }
 
-// JUST HORRENDOUS
-fn tree_scenic_score(grid: &AsciiGrid, x: usize, y: usize) -> usize {
-    let mut score = 1;
-    let height = grid[(x, y)];
+const HEAT_MAP_RAMP: &str = " .:-=+*#%@";
 
-    let mut i = x;
-    while i > 0 {
-        i -= 1;
-        if grid[(i, y)] >= height {
-            break;
-        }
+// Scenic scores span several orders of magnitude, so scale them
+// logarithmically to a level between 0 and 1.
+fn heat_level(score: usize, max_score: usize) -> f64 {
+    if max_score == 0 {
+        return 0.0;
     }
-    score *= x - i;
-
-    let mut i = x;
-    while i < grid.width - 1 {
-        i += 1;
-        if grid[(i, y)] >= height {
-            break;
-        }
-    }
-    score *= i - x;
+    (1.0 + score as f64).ln() / (1.0 + max_score as f64).ln()
+}
 
-    let mut i = y;
-    while i > 0 {
-        i -= 1;
-        if grid[(x, i)] >= height {
-            break;
-        }
-    }
-    score *= y - i;
+// black -> red -> yellow -> white
+fn heat_color(level: f64) -> [u8; 3] {
+    let channel = |start: f64| ((level * 3.0 - start).clamp(0.0, 1.0) * 255.0) as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
 
-    let mut i = y;
-    while i < grid.height - 1 {
-        i += 1;
-        if grid[(x, i)] >= height {
-            break;
-        }
-    }
+fn calc_scenic_scores(grid: &VecGrid<u8>) -> VecGrid<usize> {
+    let (width, height) = (grid.width(), grid.height());
+    let mut scores = VecGrid::full(width, height, 1);
 
-    score *= i - y;
+    // looking north
+    mark_viewing_distance(grid, &mut scores, width, height, |w, d| (w, d));
+    // looking south
+    mark_viewing_distance(grid, &mut scores, width, height, |w, d| (w, height - 1 - d));
+    // looking west
+    mark_viewing_distance(grid, &mut scores, height, width, |w, d| (d, w));
+    // looking east
+    mark_viewing_distance(grid, &mut scores, height, width, |w, d| (width - 1 - d, w));
 
-    score
+    scores
 }
 
-struct AsciiGrid {
-    bytes: Vec<u8>,
+// Sweeps each line in the same way as `mark_visible`, multiplying every
+// tree's score by how far it can see back towards the start of the line.
+// The stack holds the trees that could still block the view of a later
+// tree, with strictly decreasing heights from bottom to top, so every tree
+// is pushed and popped at most once.
+fn mark_viewing_distance<F>(
+    grid: &VecGrid<u8>,
+    scores: &mut VecGrid<usize>,
     width: usize,
-    height: usize,
-    line_width: usize,
-}
-
-impl Index<GridCoords> for AsciiGrid {
-    type Output = u8;
-
-    fn index(&self, (x, y): GridCoords) -> &Self::Output {
-        &self.bytes[x + self.line_width * y]
+    depth: usize,
+    coord_fn: F,
+) where
+    F: Fn(usize, usize) -> (usize, usize),
+{
+    let mut stack: Vec<(usize, u8)> = Vec::with_capacity(depth);
+    for w in 0..width {
+        stack.clear();
+        for d in 0..depth {
+            let coord = coord_fn(w, d);
+            let height = grid[coord];
+            while matches!(stack.last(), Some(&(_, h)) if h < height) {
+                stack.pop();
+            }
+            let viewing_distance = match stack.last() {
+                // view is blocked by a tree at least as tall
+                Some(&(blocker, _)) => d - blocker,
+                // view reaches the edge
+                None => d,
+            };
+            scores[coord] *= viewing_distance;
+            stack.push((d, height));
+        }
     }
 }
 
-impl AsciiGrid {
-    fn from_bytes(bytes: Vec<u8>) -> Self {
-        let width = find_first_newline(&bytes);
-        let line_width = width + 1;
-        let height = bytes.len() / line_width;
-        AsciiGrid {
-            bytes,
-            width,
-            height,
-            line_width,
+// parses the digits of the input into numeric tree heights
+fn parse_heights(bytes: &[u8]) -> VecGrid<u8> {
+    let width = find_first_newline(bytes);
+    let line_width = width + 1;
+    let height = bytes.len() / line_width;
+    let mut grid = VecGrid::full(width, height, 0);
+    for y in 0..height {
+        for x in 0..width {
+            let b = bytes[x + line_width * y];
+            assert!(b.is_ascii_digit(), "invalid tree height {:?}", b as char);
+            grid[(x, y)] = b - b'0';
         }
     }
+    grid
 }
 
 fn find_first_newline(bytes: &[u8]) -> usize {
@@ -147,3 +181,122 @@ fn find_first_newline(bytes: &[u8]) -> usize {
 
     panic!("no newline found")
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[u8] = b"30373\n25512\n65332\n33549\n35390\n";
+
+    // the original implementation, scanning outward from every tree
+    fn tree_scenic_score(grid: &VecGrid<u8>, x: usize, y: usize) -> usize {
+        let mut score = 1;
+        let height = grid[(x, y)];
+
+        let mut i = x;
+        while i > 0 {
+            i -= 1;
+            if grid[(i, y)] >= height {
+                break;
+            }
+        }
+        score *= x - i;
+
+        let mut i = x;
+        while i < grid.width() - 1 {
+            i += 1;
+            if grid[(i, y)] >= height {
+                break;
+            }
+        }
+        score *= i - x;
+
+        let mut i = y;
+        while i > 0 {
+            i -= 1;
+            if grid[(x, i)] >= height {
+                break;
+            }
+        }
+        score *= y - i;
+
+        let mut i = y;
+        while i < grid.height() - 1 {
+            i += 1;
+            if grid[(x, i)] >= height {
+                break;
+            }
+        }
+
+        score *= i - y;
+
+        score
+    }
+
+    fn assert_matches_reference(grid: &VecGrid<u8>) {
+        let scores = calc_scenic_scores(grid);
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                assert_eq!(
+                    scores[(x, y)],
+                    tree_scenic_score(grid, x, y),
+                    "at {:?}",
+                    (x, y)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_scenic_scores_example() {
+        let grid = parse_heights(EXAMPLE);
+        let scores = calc_scenic_scores(&grid);
+        assert_eq!(scores[(2, 1)], 4);
+        assert_eq!(scores[(2, 3)], 8);
+        assert_matches_reference(&grid);
+    }
+
+    #[test]
+    fn test_scenic_scores_match_reference() {
+        // pseudo-random forests with lots of equal heights
+        let mut state: u32 = 12345;
+        for (width, height) in [(1, 1), (1, 7), (9, 1), (13, 8), (30, 30)] {
+            let mut bytes = Vec::new();
+            for _ in 0..height {
+                for _ in 0..width {
+                    state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                    bytes.push(b'0' + ((state >> 16) % 6) as u8);
+                }
+                bytes.push(b'\n');
+            }
+            assert_matches_reference(&parse_heights(&bytes));
+        }
+    }
+
+    fn count_visible(forest: &[u8]) -> usize {
+        let grid = parse_heights(forest);
+        calc_visibility(&grid).iter().filter(|&&v| v).count()
+    }
+
+    #[test]
+    fn test_visibility() {
+        assert_eq!(count_visible(EXAMPLE), 21);
+        // edge trees are visible, even with height zero
+        assert_eq!(count_visible(b"000\n000\n000\n"), 8);
+        assert_eq!(count_visible(b"0000\n0100\n0000\n"), 11);
+        // in a single row or column, every tree is on the edge
+        assert_eq!(count_visible(b"30073\n"), 5);
+        assert_eq!(count_visible(b"3\n0\n0\n7\n3\n"), 5);
+        assert_eq!(count_visible(b"0\n"), 1);
+    }
+
+    #[test]
+    fn test_heat_level() {
+        assert_eq!(heat_level(0, 1000), 0.0);
+        assert_eq!(heat_level(1000, 1000), 1.0);
+        assert!(heat_level(10, 1000) < heat_level(100, 1000));
+        assert_eq!(heat_level(0, 0), 0.0);
+        assert_eq!(heat_color(0.0), [0, 0, 0]);
+        assert_eq!(heat_color(1.0), [255, 255, 255]);
+    }
+}
@@ -1,33 +1,91 @@
 use std::collections::HashSet;
 
-use crate::utils::Reader;
+use crate::utils::{Options, Reader};
 
-pub fn part1(input_path: &str) {
-    calc_num_tail_positions(input_path, 2);
+pub const FLAGS: &[&str] = &["--all-knots", "--animate"];
+
+pub fn part1(input_path: &str, options: &Options) {
+    calc_num_tail_positions(input_path, 2, options);
 }
 
-pub fn part2(input_path: &str) {
-    calc_num_tail_positions(input_path, 10);
+pub fn part2(input_path: &str, options: &Options) {
+    calc_num_tail_positions(input_path, 10, options);
 }
 
-fn calc_num_tail_positions(input_path: &str, rope_length: usize) {
+fn calc_num_tail_positions(input_path: &str, rope_length: usize, options: &Options) {
     let bytes = std::fs::read(input_path).unwrap();
-    let mut reader = Reader::from_bytes(&bytes);
+    let animate = options.flag("--animate");
+    // only the tail is tracked, unless every knot is asked for
+    let first_tracked = if options.flag("--all-knots") {
+        0
+    } else {
+        rope_length - 1
+    };
 
-    let mut rope_state = RopeState::new(rope_length);
+    let mut bounds = Bounds::around(Coord { x: 0, y: 0 });
+    if animate {
+        println!("== Initial State ==\n");
+        println!("{}", RopeState::new(rope_length).render(&bounds));
+    }
 
-    let mut tail_positions = HashSet::new();
-    tail_positions.insert(*rope_state.nodes.last().unwrap());
+    let num_visited = simulate(
+        &bytes,
+        rope_length,
+        first_tracked,
+        |(direction, count), step, rope_state| {
+            if !animate {
+                return;
+            }
+            if step == 0 {
+                println!("== {} {} ==\n", direction.name(), count);
+            }
+            for &node in &rope_state.nodes {
+                bounds.extend(node);
+            }
+            println!("{}", rope_state.render(&bounds));
+        },
+    );
+
+    if first_tracked == 0 {
+        for (i, n) in num_visited.iter().enumerate() {
+            println!("{}: {}", knot_label(i, rope_length), n);
+        }
+    }
+
+    println!("{}", num_visited.last().unwrap());
+}
+
+/// Moves the rope through every motion, calling `after_step` with the motion,
+/// the 0-based step within it and the new state.
+/// Returns the number of positions visited by each knot from `first_tracked`
+/// up to the tail.
+fn simulate<F>(
+    bytes: &[u8],
+    rope_length: usize,
+    first_tracked: usize,
+    mut after_step: F,
+) -> Vec<usize>
+where
+    F: FnMut((Direction, usize), usize, &RopeState),
+{
+    let mut reader = Reader::from_bytes(bytes);
+    let mut rope_state = RopeState::new(rope_length);
+    let mut knot_positions = vec![HashSet::new(); rope_length - first_tracked];
+    rope_state.record_positions(first_tracked, &mut knot_positions);
 
     while reader.has_next() {
-        let (direction, count) = read_motion(&mut reader);
-        for _ in 0..count {
-            rope_state.step(direction);
-            tail_positions.insert(*rope_state.nodes.last().unwrap());
+        let motion = read_motion(&mut reader);
+        for step in 0..motion.1 {
+            rope_state.step(motion.0);
+            rope_state.record_positions(first_tracked, &mut knot_positions);
+            after_step(motion, step, &rope_state);
         }
     }
 
-    println!("{}", tail_positions.len());
+    knot_positions
+        .iter()
+        .map(|positions| positions.len())
+        .collect()
 }
 
 #[derive(PartialEq, Eq, Hash, Copy, Clone)]
@@ -40,12 +98,27 @@ struct RopeState {
     nodes: Vec<Coord>,
 }
 
-#[derive(PartialEq, Eq, Copy, Clone)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+struct Direction {
+    dx: isize,
+    dy: isize,
+}
+
+impl Direction {
+    fn name(&self) -> String {
+        let mut name = String::new();
+        match self.dy {
+            1 => name.push('U'),
+            -1 => name.push('D'),
+            _ => {}
+        }
+        match self.dx {
+            1 => name.push('R'),
+            -1 => name.push('L'),
+            _ => {}
+        }
+        name
+    }
 }
 
 impl RopeState {
@@ -56,20 +129,8 @@ impl RopeState {
     }
 
     fn step(&mut self, direction: Direction) {
-        match direction {
-            Direction::Up => {
-                self.nodes[0].y += 1;
-            }
-            Direction::Down => {
-                self.nodes[0].y -= 1;
-            }
-            Direction::Left => {
-                self.nodes[0].x -= 1;
-            }
-            Direction::Right => {
-                self.nodes[0].x += 1;
-            }
-        }
+        self.nodes[0].x += direction.dx;
+        self.nodes[0].y += direction.dy;
         for i in 1..self.nodes.len() {
             let dx = self.nodes[i - 1].x - self.nodes[i].x;
             let dy = self.nodes[i - 1].y - self.nodes[i].y;
@@ -82,18 +143,148 @@ impl RopeState {
             }
         }
     }
+
+    fn record_positions(&self, first_tracked: usize, knot_positions: &mut [HashSet<Coord>]) {
+        for (positions, &node) in knot_positions.iter_mut().zip(&self.nodes[first_tracked..]) {
+            positions.insert(node);
+        }
+    }
+
+    /// Draws the rope like the puzzle text does: knots closer to the head
+    /// are drawn on top, and `s` marks the starting position.
+    fn render(&self, bounds: &Bounds) -> String {
+        let mut out = String::new();
+        for y in (bounds.min.y..=bounds.max.y).rev() {
+            for x in bounds.min.x..=bounds.max.x {
+                let coord = Coord { x, y };
+                let c = match self.nodes.iter().position(|&node| node == coord) {
+                    Some(i) => knot_label(i, self.nodes.len()),
+                    None if coord == (Coord { x: 0, y: 0 }) => 's',
+                    None => '.',
+                };
+                out.push(c);
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+// labels as used in the puzzle text
+fn knot_label(i: usize, rope_length: usize) -> char {
+    if i == 0 {
+        'H'
+    } else if rope_length == 2 {
+        'T'
+    } else {
+        char::from_digit(i as u32, 36).unwrap_or('#')
+    }
+}
+
+// the area shown in the animation, which only ever grows so that frames
+// line up with each other
+struct Bounds {
+    min: Coord,
+    max: Coord,
+}
+
+impl Bounds {
+    fn around(coord: Coord) -> Self {
+        Bounds {
+            min: coord,
+            max: coord,
+        }
+    }
+
+    fn extend(&mut self, coord: Coord) {
+        self.min.x = self.min.x.min(coord.x);
+        self.min.y = self.min.y.min(coord.y);
+        self.max.x = self.max.x.max(coord.x);
+        self.max.y = self.max.y.max(coord.y);
+    }
 }
 
 fn read_motion(reader: &mut Reader) -> (Direction, usize) {
-    let direction = match reader.take_byte() {
-        b'U' => Direction::Up,
-        b'D' => Direction::Down,
-        b'L' => Direction::Left,
-        b'R' => Direction::Right,
-        _ => panic!("invalid direction"),
-    };
+    let direction = parse_direction(reader.take_while(|c| c != b' ')).expect("invalid direction");
     reader.skip_lit(b" ");
     let count = reader.read_usize();
     reader.skip_lit(b"\n");
     (direction, count)
 }
+
+// directions are a vertical and/or horizontal component, e.g. `U`, `L`, `UR`
+fn parse_direction(name: &[u8]) -> Option<Direction> {
+    let mut dx = None;
+    let mut dy = None;
+    for &c in name {
+        let (component, value) = match c {
+            b'U' => (&mut dy, 1),
+            b'D' => (&mut dy, -1),
+            b'L' => (&mut dx, -1),
+            b'R' => (&mut dx, 1),
+            _ => return None,
+        };
+        // each component may only be given once
+        if component.replace(value).is_some() {
+            return None;
+        }
+    }
+    if dx.is_none() && dy.is_none() {
+        return None;
+    }
+    Some(Direction {
+        dx: dx.unwrap_or(0),
+        dy: dy.unwrap_or(0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[u8] = b"R 4\nU 4\nL 3\nD 1\nR 4\nD 1\nL 5\nR 2\n";
+    const LARGER_EXAMPLE: &[u8] = b"R 5\nU 8\nL 8\nD 3\nR 17\nD 10\nL 25\nU 20\n";
+
+    #[test]
+    fn test_parse_motion() {
+        let mut reader = Reader::from_bytes(b"UR 3\nDL 1\n");
+        assert_eq!(read_motion(&mut reader), (Direction { dx: 1, dy: 1 }, 3));
+        assert_eq!(read_motion(&mut reader), (Direction { dx: -1, dy: -1 }, 1));
+        assert!(parse_direction(b"UD").is_none());
+        assert!(parse_direction(b"UU").is_none());
+        assert!(parse_direction(b"").is_none());
+        assert!(parse_direction(b"X").is_none());
+    }
+
+    #[test]
+    fn test_examples() {
+        let tail_visits =
+            |bytes, rope_length| simulate(bytes, rope_length, rope_length - 1, |_, _, _| {});
+        assert_eq!(tail_visits(EXAMPLE, 2), vec![13]);
+        assert_eq!(tail_visits(EXAMPLE, 10), vec![1]);
+        assert_eq!(tail_visits(LARGER_EXAMPLE, 10), vec![36]);
+
+        let all_visits = simulate(EXAMPLE, 2, 0, |_, _, _| {});
+        assert_eq!(all_visits.len(), 2);
+        assert_eq!(all_visits[1], 13);
+    }
+
+    #[test]
+    fn test_render() {
+        let bounds = Bounds {
+            min: Coord { x: 0, y: 0 },
+            max: Coord { x: 5, y: 4 },
+        };
+        let mut frames = Vec::new();
+        simulate(b"R 4\n", 2, 1, |_, _, rope_state| {
+            frames.push(rope_state.render(&bounds))
+        });
+        assert_eq!(frames[3], "......\n......\n......\n......\ns..TH.\n");
+
+        let mut frames = Vec::new();
+        simulate(b"R 4\n", 10, 9, |_, _, rope_state| {
+            frames.push(rope_state.render(&bounds))
+        });
+        assert_eq!(frames[3], "......\n......\n......\n......\n4321H.\n");
+    }
+}
@@ -1,66 +1,274 @@
-use crate::utils::Reader;
-
-pub fn part1(input_path: &str) {
-    let register_value_log = calc_register_value_log(input_path, 220);
-    let answer: isize = [20usize, 60, 100, 140, 180, 220]
-        .into_iter()
-        .map(|i| i as isize * register_value_log[i - 1])
-        .sum();
+use std::fmt;
+
+use crate::utils::{Options, Reader};
+
+pub const FLAGS: &[&str] = &[
+    "--break-cycle=",
+    "--break-x=",
+    "--disassemble",
+    "--height=",
+    "--sample-cycles=",
+    "--sprite-width=",
+    "--trace",
+    "--width=",
+];
+
+const DEFAULT_SAMPLE_CYCLES: [usize; 6] = [20, 60, 100, 140, 180, 220];
+
+pub fn part1(input_path: &str, options: &Options) {
+    let program = read_program(input_path, options);
+    let sample_cycles = options
+        .parse_list("--sample-cycles")
+        .unwrap_or_else(|| DEFAULT_SAMPLE_CYCLES.to_vec());
+    let last_sample = sample_cycles.iter().copied().max().unwrap_or(0);
+
+    let mut cpu = configure_cpu(&program, options);
+    for &cycle in &sample_cycles {
+        cpu.add_breakpoint(Breakpoint::Cycle(cycle));
+    }
+
+    let mut answer: isize = 0;
+    let mut unsampled = sample_cycles.clone();
+    while let StopReason::Breakpoint(state) = cpu.run() {
+        if sample_cycles.contains(&state.cycle) {
+            answer += state.cycle as isize * state.x;
+            unsampled.retain(|&cycle| cycle != state.cycle);
+        } else {
+            println!("breakpoint: {}", state);
+        }
+        if state.cycle >= last_sample {
+            break;
+        }
+    }
+    assert!(
+        unsampled.is_empty(),
+        "program halted before sample cycles {:?}",
+        unsampled
+    );
     println!("{}", answer);
 }
 
-pub fn part2(input_path: &str) {
-    let register_value_log = calc_register_value_log(input_path, 240);
-    //  additional 6 for newlines
-    let crt: String = register_value_log
-        .into_iter()
-        .enumerate()
-        .map(|(i, x)| {
-            let pos = i as isize % 40;
-            if x - 1 <= pos && pos <= x + 1 {
-                '#'
-            } else {
-                '.'
+pub fn part2(input_path: &str, options: &Options) {
+    let program = read_program(input_path, options);
+    let config = CrtConfig::from_options(options);
+
+    let register_value_log =
+        calc_register_value_log(configure_cpu(&program, options), config.num_pixels());
+    let crt = config.render(&register_value_log);
+    match recognize_letters(&crt, config.width) {
+        Some(letters) => println!("{}", letters),
+        None => {
+            for row in crt.as_bytes().chunks(config.width) {
+                println!("{}", std::str::from_utf8(row).unwrap());
             }
-        })
-        .collect();
-    for i in 0..6 {
-        println!("{}", &crt[40 * i..40 * (i + 1)]);
+        }
     }
 }
 
-fn calc_register_value_log(input_path: &str, length: usize) -> Vec<isize> {
+struct CrtConfig {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+}
+
+impl CrtConfig {
+    fn from_options(options: &Options) -> Self {
+        let config = CrtConfig {
+            width: options.parse_or("--width", 40),
+            height: options.parse_or("--height", 6),
+            sprite_width: options.parse_or("--sprite-width", 3),
+        };
+        assert!(config.width > 0, "--width must be positive");
+        assert!(config.height > 0, "--height must be positive");
+        assert!(config.sprite_width > 0, "--sprite-width must be positive");
+        config
+    }
+
+    fn num_pixels(&self) -> usize {
+        self.width * self.height
+    }
+
+    fn sprite_covers(&self, x: isize, pos: isize) -> bool {
+        // the sprite is centered on x, leaning right for even widths
+        let left = x - (self.sprite_width as isize - 1) / 2;
+        left <= pos && pos < left + self.sprite_width as isize
+    }
+
+    /// Draw one pixel per cycle, row by row, without line breaks
+    fn render(&self, register_value_log: &[isize]) -> String {
+        register_value_log
+            .iter()
+            .enumerate()
+            .map(|(i, &x)| {
+                let pos = (i % self.width) as isize;
+                if self.sprite_covers(x, pos) {
+                    '#'
+                } else {
+                    '.'
+                }
+            })
+            .collect()
+    }
+}
+
+fn read_program(input_path: &str, options: &Options) -> Vec<Instruction> {
     let bytes = std::fs::read(input_path).unwrap();
+    let program = assemble(&bytes);
+    if options.flag("--disassemble") {
+        print!("{}", disassemble(&program));
+    }
+    program
+}
+
+// set up tracing and any user-requested breakpoints
+fn configure_cpu<'a>(program: &'a [Instruction], options: &Options) -> Cpu<'a> {
+    let mut cpu = Cpu::new(program);
+    cpu.set_trace(options.flag("--trace"));
+    for cycle in options.parse_list("--break-cycle").unwrap_or_default() {
+        cpu.add_breakpoint(Breakpoint::Cycle(cycle));
+    }
+    for x in options.parse_list("--break-x").unwrap_or_default() {
+        cpu.add_breakpoint(Breakpoint::RegisterValue(x));
+    }
+    cpu
+}
 
-    let mut reader = Reader::from_bytes(&bytes);
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+// glyphs are separated by a single blank column
+const GLYPH_SPACING: usize = GLYPH_WIDTH + 1;
 
-    // +1 because we could overshoot by one, since the longest instruction
-    // takes two cycles
-    let mut register_value_log = Vec::with_capacity(length + 1);
+const FONT: &[(char, [&str; GLYPH_HEIGHT])] = &[
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
 
-    let mut register_value: isize = 1;
+/// Read the letters off a rendered CRT image of the given width,
+/// using the standard Advent of Code font.
+/// Returns None when the image contains a glyph that is not in the font,
+/// lit pixels between or after the glyphs, or no room for any glyph.
+fn recognize_letters(crt: &str, width: usize) -> Option<String> {
+    let rows: Vec<&str> = crt
+        .as_bytes()
+        .chunks(width)
+        .map(|row| std::str::from_utf8(row).unwrap())
+        .collect();
+    if rows.len() != GLYPH_HEIGHT || rows.iter().any(|row| row.len() != width) {
+        return None;
+    }
 
-    while reader.has_next() && register_value_log.len() < length {
-        let instruction = read_instruction(&mut reader);
+    // the spacer columns and any columns left over after the last glyph
+    let num_glyphs = width / GLYPH_SPACING;
+    if num_glyphs == 0 {
+        return None;
+    }
+    let is_blank_column =
+        |x: usize| x >= num_glyphs * GLYPH_SPACING || x % GLYPH_SPACING == GLYPH_WIDTH;
+    let blank_columns_lit = rows.iter().any(|row| {
+        row.bytes()
+            .enumerate()
+            .any(|(x, c)| is_blank_column(x) && c != b'.')
+    });
+    if blank_columns_lit {
+        return None;
+    }
 
-        match instruction {
-            Instruction::Noop => register_value_log.push(register_value),
-            Instruction::Addx(num) => {
-                register_value_log.push(register_value);
-                register_value_log.push(register_value);
-                register_value += num;
+    (0..num_glyphs)
+        .map(|n| {
+            let offset = n * GLYPH_SPACING;
+            FONT.iter()
+                .find(|(_, glyph)| {
+                    glyph
+                        .iter()
+                        .zip(rows.iter())
+                        .all(|(glyph_row, row)| &row[offset..offset + GLYPH_WIDTH] == *glyph_row)
+                })
+                .map(|&(c, _)| c)
+        })
+        .collect()
+}
+
+/// Value of the X register during each of the first `length` cycles
+/// (or fewer, if the program halts before that).
+fn calc_register_value_log(mut cpu: Cpu, length: usize) -> Vec<isize> {
+    let mut register_value_log = Vec::with_capacity(length);
+    while register_value_log.len() < length {
+        match cpu.step() {
+            Some(state) => {
+                if cpu.is_breakpoint(&state) {
+                    println!("breakpoint: {}", state);
+                }
+                register_value_log.push(state.x);
             }
+            None => break,
         }
     }
-
     register_value_log
 }
 
-enum Instruction {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
     Addx(isize),
     Noop,
 }
 
+impl Instruction {
+    fn num_cycles(&self) -> usize {
+        match self {
+            Instruction::Addx(_) => 2,
+            Instruction::Noop => 1,
+        }
+    }
+
+    // applied when the last cycle of the instruction completes
+    fn execute(&self, x: &mut isize) {
+        match self {
+            Instruction::Addx(num) => *x += num,
+            Instruction::Noop => {}
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Addx(num) => write!(f, "addx {}", num),
+            Instruction::Noop => write!(f, "noop"),
+        }
+    }
+}
+
+pub fn assemble(bytes: &[u8]) -> Vec<Instruction> {
+    let mut reader = Reader::from_bytes(bytes);
+    let mut program = Vec::new();
+    while reader.has_next() {
+        program.push(read_instruction(&mut reader));
+    }
+    program
+}
+
+pub fn disassemble(program: &[Instruction]) -> String {
+    program
+        .iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
 fn read_instruction(reader: &mut Reader) -> Instruction {
     match reader.peek() {
         b'n' => {
@@ -76,3 +284,243 @@ fn read_instruction(reader: &mut Reader) -> Instruction {
         _ => panic!("bad instruction"),
     }
 }
+
+/// Snapshot of the machine while a cycle is in progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CycleState {
+    /// 1-based cycle number
+    pub cycle: usize,
+    /// value of the X register during this cycle
+    pub x: isize,
+    pub instruction: Instruction,
+}
+
+impl fmt::Display for CycleState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cycle {:>4}  X = {:>4}  {}",
+            self.cycle, self.x, self.instruction
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// break during the given cycle
+    Cycle(usize),
+    /// break during any cycle in which X holds the given value
+    RegisterValue(isize),
+}
+
+impl Breakpoint {
+    fn matches(&self, state: &CycleState) -> bool {
+        match *self {
+            Breakpoint::Cycle(cycle) => state.cycle == cycle,
+            Breakpoint::RegisterValue(x) => state.x == x,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint(CycleState),
+    Halted,
+}
+
+pub struct Cpu<'a> {
+    program: &'a [Instruction],
+    // index of the instruction currently being executed
+    pc: usize,
+    // number of cycles already spent on the current instruction
+    progress: usize,
+    cycle: usize,
+    x: isize,
+    breakpoints: Vec<Breakpoint>,
+    trace: bool,
+}
+
+impl<'a> Cpu<'a> {
+    pub fn new(program: &'a [Instruction]) -> Self {
+        Cpu {
+            program,
+            pc: 0,
+            progress: 0,
+            cycle: 0,
+            x: 1,
+            breakpoints: Vec::new(),
+            trace: false,
+        }
+    }
+
+    /// Print the machine state for every executed cycle
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Execute a single cycle, returning the machine state during that cycle.
+    pub fn step(&mut self) -> Option<CycleState> {
+        let instruction = *self.program.get(self.pc)?;
+        self.cycle += 1;
+        let state = CycleState {
+            cycle: self.cycle,
+            x: self.x,
+            instruction,
+        };
+
+        self.progress += 1;
+        if self.progress == instruction.num_cycles() {
+            instruction.execute(&mut self.x);
+            self.pc += 1;
+            self.progress = 0;
+        }
+
+        if self.trace {
+            println!("{}", state);
+        }
+        Some(state)
+    }
+
+    pub fn is_breakpoint(&self, state: &CycleState) -> bool {
+        self.breakpoints.iter().any(|b| b.matches(state))
+    }
+
+    /// Run until a breakpoint is hit or the program ends.
+    /// The cycle that triggered the breakpoint has completed when this returns,
+    /// so calling `run` again resumes with the next cycle.
+    pub fn run(&mut self) -> StopReason {
+        while let Some(state) = self.step() {
+            if self.is_breakpoint(&state) {
+                return StopReason::Breakpoint(state);
+            }
+        }
+        StopReason::Halted
+    }
+}
+
+impl<'a> Iterator for Cpu<'a> {
+    type Item = CycleState;
+
+    fn next(&mut self) -> Option<CycleState> {
+        self.step()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL_PROGRAM: &[u8] = b"noop\naddx 3\naddx -5\n";
+
+    #[test]
+    fn test_cycle_accurate_register_values() {
+        let program = assemble(SMALL_PROGRAM);
+        let xs: Vec<isize> = Cpu::new(&program).map(|state| state.x).collect();
+        assert_eq!(xs, vec![1, 1, 1, 4, 4]);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let program = assemble(SMALL_PROGRAM);
+        let mut cpu = Cpu::new(&program);
+        cpu.add_breakpoint(Breakpoint::Cycle(2));
+        cpu.add_breakpoint(Breakpoint::RegisterValue(4));
+
+        let hits: Vec<usize> = std::iter::from_fn(|| match cpu.run() {
+            StopReason::Breakpoint(state) => Some(state.cycle),
+            StopReason::Halted => None,
+        })
+        .collect();
+        assert_eq!(hits, vec![2, 4, 5]);
+    }
+
+    #[test]
+    fn test_recognize_letters() {
+        let letters = "EHZ";
+        let rows: Vec<String> = (0..GLYPH_HEIGHT)
+            .map(|y| {
+                letters
+                    .chars()
+                    .map(|c| {
+                        let (_, glyph) = FONT.iter().find(|(g, _)| *g == c).unwrap();
+                        format!("{}.", glyph[y])
+                    })
+                    .collect()
+            })
+            .collect();
+        let width = letters.len() * GLYPH_SPACING;
+        let mut crt = rows.concat();
+        assert_eq!(recognize_letters(&crt, width), Some(letters.to_string()));
+
+        // light up the spacer after the first glyph
+        let mut lit_spacer = crt.clone();
+        lit_spacer.replace_range(GLYPH_WIDTH..GLYPH_WIDTH + 1, "#");
+        assert_eq!(recognize_letters(&lit_spacer, width), None);
+
+        // two columns left over after the last glyph, which must stay blank
+        let padded: String = rows.iter().map(|row| format!("{}..", row)).collect();
+        assert_eq!(
+            recognize_letters(&padded, width + 2),
+            Some(letters.to_string())
+        );
+        let mut lit_padding = padded.clone();
+        lit_padding.replace_range(width + 1..width + 2, "#");
+        assert_eq!(recognize_letters(&lit_padding, width + 2), None);
+
+        // too narrow for a single glyph
+        let dark = ".".repeat(GLYPH_WIDTH * GLYPH_HEIGHT);
+        assert_eq!(recognize_letters(&dark, GLYPH_WIDTH), None);
+
+        // corrupt a pixel of the middle glyph
+        crt.replace_range(GLYPH_SPACING..GLYPH_SPACING + 1, ".");
+        assert_eq!(recognize_letters(&crt, width), None);
+    }
+
+    #[test]
+    fn test_crt_geometry() {
+        let odd = CrtConfig {
+            width: 40,
+            height: 6,
+            sprite_width: 3,
+        };
+        let covered: Vec<isize> = (0..10).filter(|&pos| odd.sprite_covers(5, pos)).collect();
+        assert_eq!(covered, vec![4, 5, 6]);
+
+        let even = CrtConfig {
+            sprite_width: 4,
+            ..odd
+        };
+        let covered: Vec<isize> = (0..10).filter(|&pos| even.sprite_covers(5, pos)).collect();
+        assert_eq!(covered, vec![4, 5, 6, 7]);
+        assert!(even.sprite_covers(-2, 0));
+        assert!(!even.sprite_covers(-3, 0));
+
+        // positions restart at every row of 5 pixels
+        let narrow = CrtConfig {
+            width: 5,
+            height: 2,
+            sprite_width: 3,
+        };
+        assert_eq!(narrow.num_pixels(), 10);
+        let register_value_log = [1, 1, 1, 1, 1, 3, 3, 3, 3, 3];
+        assert_eq!(narrow.render(&register_value_log), "###....###");
+    }
+
+    #[test]
+    fn test_assembler_round_trip() {
+        let program = assemble(SMALL_PROGRAM);
+        assert_eq!(
+            program,
+            vec![
+                Instruction::Noop,
+                Instruction::Addx(3),
+                Instruction::Addx(-5)
+            ]
+        );
+        assert_eq!(disassemble(&program).as_bytes(), SMALL_PROGRAM);
+    }
+}
@@ -1,59 +1,186 @@
 use std::cmp::Reverse;
 
-use crate::utils::Reader;
+use crate::utils::{Options, Reader};
 
-pub fn part1(input_path: &str) {
-    calc_monkey_business_level(input_path, 20, Some(3));
+pub const FLAGS: &[&str] = &["--rounds="];
+
+pub fn part1(input_path: &str, options: &Options) {
+    calc_monkey_business_level(input_path, 20, Some(3), options);
 }
 
-pub fn part2(input_path: &str) {
-    calc_monkey_business_level(input_path, 10000, None);
+pub fn part2(input_path: &str, options: &Options) {
+    calc_monkey_business_level(input_path, 10000, None, options);
 }
 
-fn calc_monkey_business_level(input_path: &str, n_rounds: usize, worry_divisor: Option<usize>) {
-    let mut monkeys = parse_input(input_path);
+fn calc_monkey_business_level(
+    input_path: &str,
+    n_rounds: usize,
+    worry_divisor: Option<isize>,
+    options: &Options,
+) {
+    let bytes = std::fs::read(input_path).unwrap();
+    let mut monkeys = parse_monkeys(&bytes);
+    let report_rounds = options.parse_list("--rounds").unwrap_or_default();
 
-    let base: usize = monkeys.iter().map(|m| m.test.divisor).product();
+    let inspection_table = simulate(
+        &mut monkeys,
+        n_rounds,
+        worry_divisor,
+        |round, monkeys, num_inspections| {
+            if report_rounds.contains(&round) {
+                print_round_report(round, monkeys, num_inspections);
+            }
+        },
+    );
 
+    let mut num_inspections = inspection_table.last().cloned().unwrap_or_default();
+    num_inspections.sort_by_key(|&n| Reverse(n));
+    println!("{}", num_inspections[0] * num_inspections[1]);
+}
+
+/// Runs the given number of rounds, calling `after_round` with the 1-based
+/// round number when each round completes.
+/// Returns, for every round, the total number of inspections per monkey
+/// up to and including that round.
+fn simulate<F>(
+    monkeys: &mut [Monkey],
+    n_rounds: usize,
+    worry_divisor: Option<isize>,
+    mut after_round: F,
+) -> Vec<Vec<usize>>
+where
+    F: FnMut(usize, &[Monkey], &[usize]),
+{
+    // worry levels can be reduced modulo any common multiple of the
+    // divisors; the least one keeps intermediate values as small as possible
+    let base: isize = monkeys
+        .iter()
+        .map(|m| m.test.divisor as isize)
+        .fold(1, |acc, divisor| {
+            (acc / gcd(acc, divisor))
+                .checked_mul(divisor)
+                .expect("lcm of divisors overflowed")
+        });
+
+    let mut inspection_table = Vec::with_capacity(n_rounds);
     let mut num_inspections = vec![0; monkeys.len()];
-    for _round in 0..n_rounds {
+    for round in 1..=n_rounds {
         for monkey_num in 0..monkeys.len() {
-            num_inspections[monkey_num] += monkeys[monkey_num].items.len();
-            while let Some(mut worry) = monkeys[monkey_num].items.pop() {
-                worry = monkeys[monkey_num].operation.apply(worry);
-                if let Some(divisor) = worry_divisor {
-                    worry /= divisor;
-                }
-                worry %= base;
+            // take the items out, so that they are inspected in order
+            let items = std::mem::take(&mut monkeys[monkey_num].items);
+            num_inspections[monkey_num] += items.len();
+            for worry in items {
+                let worry =
+                    update_worry_level(&monkeys[monkey_num].operation, worry, worry_divisor, base);
                 let target_monkey = monkeys[monkey_num].test.get_target_monkey(worry);
                 monkeys[target_monkey].items.push(worry);
             }
         }
+        after_round(round, monkeys, &num_inspections);
+        inspection_table.push(num_inspections.clone());
     }
-    num_inspections.sort_by_key(|&n| Reverse(n));
-    println!("{}", num_inspections[0] * num_inspections[1]);
+    inspection_table
+}
+
+// mirrors the worked example in the puzzle text
+fn print_round_report(round: usize, monkeys: &[Monkey], num_inspections: &[usize]) {
+    println!("== After round {} ==", round);
+    for (monkey_num, monkey) in monkeys.iter().enumerate() {
+        let items: Vec<String> = monkey.items.iter().map(|item| item.to_string()).collect();
+        println!("Monkey {}: {}", monkey_num, items.join(", "));
+    }
+    for (monkey_num, n) in num_inspections.iter().enumerate() {
+        println!("Monkey {} inspected items {} times.", monkey_num, n);
+    }
+    println!();
+}
+
+fn update_worry_level(
+    operation: &MonkeyOp,
+    worry: isize,
+    worry_divisor: Option<isize>,
+    base: isize,
+) -> isize {
+    match worry_divisor {
+        // floor division does not commute with taking a remainder, so with
+        // relief the exact worry levels are kept
+        Some(divisor) => operation
+            .apply(worry)
+            .expect("worry level overflowed, relief needs the exact value")
+            .div_euclid(divisor),
+        // without relief only the remainder matters. Subtraction may yield
+        // negative worry levels, so normalize them back into [0, base)
+        None => match operation.apply(worry) {
+            Some(worry) => worry.rem_euclid(base),
+            // the operation overflowed, compute the remainder without ever
+            // overflowing
+            None => operation.apply_mod(worry, base),
+        },
+    }
+}
+
+fn gcd(mut a: isize, mut b: isize) -> isize {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
 }
 
 struct Monkey {
-    items: Vec<usize>,
+    items: Vec<isize>,
     operation: MonkeyOp,
     test: Test,
 }
 
+// Every operation is compiled to a polynomial in `old` with integer
+// coefficients. Addition, subtraction and multiplication all commute with
+// taking a remainder, so for any polynomial p and modulus m we have
+// p(old) mod m == p(old mod m) mod m. This is what makes it valid to reduce
+// worry levels modulo the least common multiple of all divisors, as long as
+// there is no relief.
 enum MonkeyOp {
-    AddConst(usize),
-    MulConst(usize),
+    AddConst(isize),
+    MulConst(isize),
     Square,
+    // coefficients, lowest degree first
+    Polynomial(Vec<isize>),
 }
 
 impl MonkeyOp {
-    fn apply(&self, old: usize) -> usize {
+    /// Returns None when the result does not fit in an isize
+    fn apply(&self, old: isize) -> Option<isize> {
         match self {
-            MonkeyOp::AddConst(num) => old + num,
-            MonkeyOp::MulConst(num) => old * num,
-            MonkeyOp::Square => old * old,
+            MonkeyOp::AddConst(num) => old.checked_add(*num),
+            MonkeyOp::MulConst(num) => old.checked_mul(*num),
+            MonkeyOp::Square => old.checked_mul(old),
+            MonkeyOp::Polynomial(coefficients) => coefficients
+                .iter()
+                .rev()
+                .try_fold(0isize, |acc, &coefficient| {
+                    acc.checked_mul(old)?.checked_add(coefficient)
+                }),
         }
     }
+
+    /// Computes the result modulo `base`, in [0, base). Every intermediate
+    /// value is reduced, so products of two values below 2^63 never
+    /// overflow 128 bits whatever the degree of the polynomial.
+    fn apply_mod(&self, old: isize, base: isize) -> isize {
+        let base = base as i128;
+        let old = (old as i128).rem_euclid(base);
+        let res = match self {
+            MonkeyOp::AddConst(num) => old + (*num as i128).rem_euclid(base),
+            MonkeyOp::MulConst(num) => old * (*num as i128).rem_euclid(base),
+            MonkeyOp::Square => old * old,
+            // Horner's method
+            MonkeyOp::Polynomial(coefficients) => {
+                coefficients.iter().rev().fold(0, |acc, &coefficient| {
+                    (acc * old + (coefficient as i128).rem_euclid(base)).rem_euclid(base)
+                })
+            }
+        };
+        res.rem_euclid(base) as isize
+    }
 }
 
 struct Test {
@@ -63,8 +190,8 @@ struct Test {
 }
 
 impl Test {
-    fn get_target_monkey(&self, worry_level: usize) -> usize {
-        if worry_level % self.divisor == 0 {
+    fn get_target_monkey(&self, worry_level: isize) -> usize {
+        if worry_level.rem_euclid(self.divisor as isize) == 0 {
             self.true_target
         } else {
             self.false_target
@@ -72,49 +199,78 @@ impl Test {
     }
 }
 
-// this struct is used only for parsing
-struct Operation {
-    left: Operand,
-    right: Operand,
-    operator: Operator,
-}
-
-enum Operand {
+// this type is used only for parsing
+enum Expr {
     Old,
-    Const(usize),
+    Const(isize),
+    BinOp(Box<Expr>, Operator, Box<Expr>),
 }
 
+#[derive(Clone, Copy)]
 enum Operator {
     Add,
+    Sub,
     Mul,
 }
 
 // "compile" the monkey operation into a more efficient form,
 // that can be evaluated with less branching.
-impl Operation {
+impl Expr {
     fn into_monkey_op(self) -> MonkeyOp {
-        use Operand::*;
-        use Operator::*;
+        let mut coefficients = self.to_polynomial();
+        while coefficients.len() > 1 && coefficients.last() == Some(&0) {
+            coefficients.pop();
+        }
 
-        let mut old = self.left;
-        let mut other = self.right;
-        if matches!(other, Old) {
-            std::mem::swap(&mut old, &mut other);
+        match coefficients[..] {
+            [num, 1] => MonkeyOp::AddConst(num),
+            [0, num] => MonkeyOp::MulConst(num),
+            [0, 0, 1] => MonkeyOp::Square,
+            _ => MonkeyOp::Polynomial(coefficients),
         }
-        assert!(matches!(old, Old));
+    }
 
-        match (self.operator, other) {
-            (Add, Const(num)) => MonkeyOp::AddConst(num),
-            (Mul, Const(num)) => MonkeyOp::MulConst(num),
-            (Add, Old) => MonkeyOp::MulConst(2),
-            (Mul, Old) => MonkeyOp::Square,
+    fn to_polynomial(&self) -> Vec<isize> {
+        match self {
+            Expr::Old => vec![0, 1],
+            Expr::Const(num) => vec![*num],
+            Expr::BinOp(left, operator, right) => {
+                let left = left.to_polynomial();
+                let right = right.to_polynomial();
+                match operator {
+                    Operator::Add => add_polynomials(&left, &right, 1),
+                    Operator::Sub => add_polynomials(&left, &right, -1),
+                    Operator::Mul => mul_polynomials(&left, &right),
+                }
+            }
         }
     }
 }
 
-fn parse_input(input_path: &str) -> Vec<Monkey> {
-    let bytes = std::fs::read(input_path).unwrap();
-    let mut reader = Reader::from_bytes(&bytes);
+// computes left + sign * right
+fn add_polynomials(left: &[isize], right: &[isize], sign: isize) -> Vec<isize> {
+    let mut res = vec![0; left.len().max(right.len())];
+    for (i, &c) in left.iter().enumerate() {
+        res[i] += c;
+    }
+    for (i, &c) in right.iter().enumerate() {
+        res[i] += sign * c;
+    }
+    res
+}
+
+fn mul_polynomials(left: &[isize], right: &[isize]) -> Vec<isize> {
+    let mut res = vec![0; left.len() + right.len() - 1];
+    for (i, &a) in left.iter().enumerate() {
+        for (j, &b) in right.iter().enumerate() {
+            res[i + j] += a * b;
+        }
+    }
+    res
+}
+
+fn parse_monkeys(bytes: &[u8]) -> Vec<Monkey> {
+    let mut reader = Reader::from_bytes(bytes);
     let mut monkeys = Vec::new();
     loop {
         let monkey = read_monkey(&mut reader);
@@ -143,11 +299,11 @@ fn read_monkey(reader: &mut Reader) -> Monkey {
     }
 }
 
-fn read_items(reader: &mut Reader) -> Vec<usize> {
+fn read_items(reader: &mut Reader) -> Vec<isize> {
     reader.skip_lit(b"  Starting items: ");
-    let mut items: Vec<usize> = Vec::new();
+    let mut items: Vec<isize> = Vec::new();
     loop {
-        let item = reader.read_usize();
+        let item = reader.read_isize();
         items.push(item);
         if reader.peek() == b'\n' {
             break;
@@ -158,37 +314,67 @@ fn read_items(reader: &mut Reader) -> Vec<usize> {
     items
 }
 
-fn read_operation(reader: &mut Reader) -> Operation {
+fn read_operation(reader: &mut Reader) -> Expr {
     reader.skip_lit(b"  Operation: new = ");
-    let left = read_operand(reader);
-    reader.skip_lit(b" ");
-    let operator = match reader.peek() {
-        b'+' => Operator::Add,
-        b'*' => Operator::Mul,
-        c => panic!("unknown operator {}", c),
-    };
-    reader.skip(1);
-    reader.skip_lit(b" ");
-    let right = read_operand(reader);
+    let expr = read_expr(reader);
     reader.skip_lit(b"\n");
-    Operation {
-        left,
-        operator,
-        right,
+    expr
+}
+
+// expr := term (('+' | '-') term)*
+fn read_expr(reader: &mut Reader) -> Expr {
+    let mut expr = read_term(reader);
+    loop {
+        skip_spaces(reader);
+        let operator = match reader.peek() {
+            b'+' => Operator::Add,
+            b'-' => Operator::Sub,
+            _ => return expr,
+        };
+        reader.skip(1);
+        let right = read_term(reader);
+        expr = Expr::BinOp(Box::new(expr), operator, Box::new(right));
     }
 }
 
-fn read_operand(reader: &mut Reader) -> Operand {
-    // TODO: can we abstract this pattern?
-    if reader.peek() == b'o' {
-        reader.skip_lit(b"old");
-        Operand::Old
-    } else {
-        let num = reader.read_usize();
-        Operand::Const(num)
+// term := factor ('*' factor)*
+fn read_term(reader: &mut Reader) -> Expr {
+    let mut expr = read_factor(reader);
+    loop {
+        skip_spaces(reader);
+        if reader.peek() != b'*' {
+            return expr;
+        }
+        reader.skip(1);
+        let right = read_factor(reader);
+        expr = Expr::BinOp(Box::new(expr), Operator::Mul, Box::new(right));
     }
 }
 
+// factor := 'old' | number | '(' expr ')'
+fn read_factor(reader: &mut Reader) -> Expr {
+    skip_spaces(reader);
+    match reader.peek() {
+        b'o' => {
+            reader.skip_lit(b"old");
+            Expr::Old
+        }
+        b'(' => {
+            reader.skip(1);
+            let expr = read_expr(reader);
+            skip_spaces(reader);
+            reader.skip_lit(b")");
+            expr
+        }
+        b'0'..=b'9' => Expr::Const(reader.read_isize()),
+        c => panic!("unexpected character {:?} in operation", c as char),
+    }
+}
+
+fn skip_spaces(reader: &mut Reader) {
+    reader.skip_while(|c| c == b' ');
+}
+
 fn read_test(reader: &mut Reader) -> Test {
     reader.skip_lit(b"  Test: divisible by ");
     let divisor = reader.read_delimited_usize(b'\n');
@@ -202,3 +388,183 @@ fn read_test(reader: &mut Reader) -> Test {
         false_target,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[u8] = b"\
+Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
+
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1
+";
+
+    #[test]
+    fn test_example_with_relief() {
+        let mut monkeys = parse_monkeys(EXAMPLE);
+        let mut held_after_first_round = Vec::new();
+        let table = simulate(&mut monkeys, 20, Some(3), |round, monkeys, _| {
+            if round == 1 {
+                held_after_first_round = monkeys.iter().map(|m| m.items.clone()).collect();
+            }
+        });
+        assert_eq!(
+            held_after_first_round,
+            vec![
+                vec![20, 23, 27, 26],
+                vec![2080, 25, 167, 207, 401, 1046],
+                vec![],
+                vec![]
+            ]
+        );
+        assert_eq!(table[19], vec![101, 95, 7, 105]);
+    }
+
+    #[test]
+    fn test_relief_keeps_exact_worry_levels() {
+        // the lcm of the divisors is 10, far below the worry levels
+        let transcript = b"\
+Monkey 0:
+  Starting items: 30, 95
+  Operation: new = old * 7
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 7
+  Operation: new = old + 4
+  Test: divisible by 5
+    If true: throw to monkey 0
+    If false: throw to monkey 0
+";
+        let mut monkeys = parse_monkeys(transcript);
+        let mut held = Vec::new();
+        let table = simulate(&mut monkeys, 2, Some(3), |_, monkeys, _| {
+            held.push(monkeys.iter().map(|m| m.items.clone()).collect::<Vec<_>>());
+        });
+        assert_eq!(held[0], vec![vec![3, 24, 75], vec![]]);
+        assert_eq!(held[1], vec![vec![3, 20, 59], vec![]]);
+        assert_eq!(table[1], vec![5, 6]);
+    }
+
+    #[test]
+    fn test_example_without_relief() {
+        let mut monkeys = parse_monkeys(EXAMPLE);
+        let table = simulate(&mut monkeys, 10000, None, |_, _, _| {});
+        assert_eq!(table[0], vec![2, 4, 3, 6]);
+        assert_eq!(table[19], vec![99, 97, 8, 103]);
+        assert_eq!(table[999], vec![5204, 4792, 199, 5192]);
+        assert_eq!(table[9999], vec![52166, 47830, 1938, 52013]);
+    }
+
+    fn compile(operation: &[u8]) -> MonkeyOp {
+        let mut reader = Reader::from_bytes(operation);
+        read_expr(&mut reader).into_monkey_op()
+    }
+
+    #[test]
+    fn test_compile_operation() {
+        assert!(matches!(compile(b"old + 6\n"), MonkeyOp::AddConst(6)));
+        assert!(matches!(compile(b"old - 6\n"), MonkeyOp::AddConst(-6)));
+        assert!(matches!(compile(b"19 * old\n"), MonkeyOp::MulConst(19)));
+        assert!(matches!(compile(b"old + old\n"), MonkeyOp::MulConst(2)));
+        assert!(matches!(compile(b"old * old\n"), MonkeyOp::Square));
+
+        let op = compile(b"2 * (old - 1) * (old + 3) - old\n");
+        assert!(matches!(&op, MonkeyOp::Polynomial(c) if c == &[-6, 3, 2]));
+        assert_eq!(op.apply(5), Some(2 * 4 * 8 - 5));
+        assert_eq!(compile(b"old * old + 3\n").apply(4), Some(19));
+        assert_eq!(compile(b"2 * old - 1\n").apply(0), Some(-1));
+    }
+
+    #[test]
+    fn test_reduction_preserves_divisibility() {
+        let base: isize = 2 * 3 * 5 * 7 * 11 * 13 * 17 * 19;
+        let ops = [
+            compile(b"old * old + 3\n"),
+            compile(b"2 * old - 1\n"),
+            compile(b"(old - 7) * (old + 2) - 4 * old\n"),
+        ];
+        for op in &ops {
+            for old in (0..100_000).step_by(997) {
+                let exact = op.apply(old).unwrap();
+                let reduced = op.apply(old.rem_euclid(base)).unwrap().rem_euclid(base);
+                assert_eq!(exact.rem_euclid(base), reduced);
+            }
+        }
+    }
+
+    #[test]
+    fn test_overflow_falls_back_to_wide_arithmetic() {
+        let square = compile(b"old * old\n");
+        // close to 2^40, so squares of reduced worry levels
+        // do not fit in 64 bits
+        let base: isize = 1_099_511_627_689;
+        let worry = base - 2;
+        assert_eq!(square.apply(worry), None);
+        assert_eq!(update_worry_level(&square, worry, None, base), 4);
+        assert_eq!(update_worry_level(&square, 7, None, base), 49);
+    }
+
+    #[test]
+    fn test_high_degree_polynomial_without_relief() {
+        let op = compile(b"old * old * old * old * old * old - 2 * old\n");
+        assert!(matches!(&op, MonkeyOp::Polynomial(c) if c.len() == 7));
+        let base: isize = 999_983 * 999_979;
+        let worry = base - 3;
+        assert_eq!(op.apply(worry), None);
+        // (-3)^6 - 2 * (-3) modulo base
+        assert_eq!(update_worry_level(&op, worry, None, base), 729 + 6);
+
+        let transcript = b"\
+Monkey 0:
+  Starting items: 999000, 7
+  Operation: new = old * old * old * old * old * old
+  Test: divisible by 999983
+    If true: throw to monkey 1
+    If false: throw to monkey 1
+
+Monkey 1:
+  Starting items: 5
+  Operation: new = old + 999000
+  Test: divisible by 999979
+    If true: throw to monkey 0
+    If false: throw to monkey 0
+";
+        let mut monkeys = parse_monkeys(transcript);
+        let table = simulate(&mut monkeys, 100, None, |_, _, _| {});
+        assert_eq!(table[99], vec![299, 300]);
+    }
+
+    #[test]
+    fn test_gcd() {
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(17, 5), 1);
+        assert_eq!(gcd(1, 23), 1);
+    }
+}
@@ -1,131 +1,344 @@
-use std::collections::VecDeque;
-
-pub fn part1(input_path: &str) {
-    let mut bytes = std::fs::read(input_path).unwrap();
-    let info = read_info(&bytes);
-    bytes[info.start_pos] = b'a';
-    bytes[info.end_pos] = b'z';
-    let mut state = State {
-        bytes: &bytes,
-        queue: VecDeque::new(),
-        backlinks: vec![None; bytes.len()],
-        line_length: info.line_length,
-    };
-    state.queue.push_back((info.start_pos, info.start_pos));
-    state.find_path(info.end_pos);
-    let steps = state.count_steps(info.end_pos);
-
-    println!("steps: {}", steps);
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use crate::utils::{GridCoords, Options, VecGrid};
+
+pub const FLAGS: &[&str] = &[
+    "--climb-cost=",
+    "--descent-cost=",
+    "--max-climb=",
+    "--max-descent=",
+    "--path",
+    "--render",
+    "--step-cost=",
+];
+
+pub fn part1(input_path: &str, options: &Options) {
+    let bytes = std::fs::read(input_path).unwrap();
+    let map = parse_heightmap(&bytes);
+    let rules = ClimbingRules::from_options(options);
+    let mut state = State::new(&map.heights, rules);
+    state.queue.push(Reverse((0, map.start, map.start)));
+    let cost = state.find_path(map.end).expect("no path to the end");
+    let path = state.path(map.end).unwrap();
+
+    report_path(&map, &path, options);
+    println!("steps: {}", path.len() - 1);
+    if !rules.is_uniform() {
+        println!("cost: {}", cost);
+    }
+}
+
+pub fn part2(input_path: &str, options: &Options) {
+    let bytes = std::fs::read(input_path).unwrap();
+    let map = parse_heightmap(&bytes);
+    let rules = ClimbingRules::from_options(options);
+    let mut state = State::new(&map.heights, rules);
+    for y in 0..map.heights.height() {
+        for x in 0..map.heights.width() {
+            if map.heights[(x, y)] == 0 {
+                state.queue.push(Reverse((0, (x, y), (x, y))));
+            }
+        }
+    }
+    let cost = state.find_path(map.end).expect("no path to the end");
+    let path = state.path(map.end).unwrap();
+
+    report_path(&map, &path, options);
+    let (x, y) = path[0];
+    println!("start: {},{}", x, y);
+    println!("steps: {}", path.len() - 1);
+    if !rules.is_uniform() {
+        println!("cost: {}", cost);
+    }
+}
+
+fn report_path(map: &Heightmap, path: &[GridCoords], options: &Options) {
+    if options.flag("--path") {
+        for &(x, y) in path {
+            println!("{},{}", x, y);
+        }
+    }
+    if options.flag("--render") {
+        print!("{}", render_path(map, path));
+    }
+}
+
+/// Draws the path over the heightmap, each step marked with an arrow
+/// pointing towards the next one.
+fn render_path(map: &Heightmap, path: &[GridCoords]) -> String {
+    let mut canvas = VecGrid::full(map.heights.width(), map.heights.height(), ' ');
+    for y in 0..map.heights.height() {
+        for x in 0..map.heights.width() {
+            canvas[(x, y)] = (b'a' + map.heights[(x, y)]) as char;
+        }
+    }
+    canvas[map.start] = 'S';
+    canvas[map.end] = 'E';
+
+    for step in path.windows(2) {
+        let ((x0, y0), (x1, y1)) = (step[0], step[1]);
+        canvas[step[0]] = if x1 > x0 {
+            '>'
+        } else if x1 < x0 {
+            '<'
+        } else if y1 > y0 {
+            'v'
+        } else {
+            '^'
+        };
+    }
+    canvas.render(|&c| c)
 }
 
-pub fn part2(input_path: &str) {
-    let mut bytes = std::fs::read(input_path).unwrap();
-    let info = read_info(&bytes);
-    bytes[info.start_pos] = b'a';
-    bytes[info.end_pos] = b'z';
-    let mut state = State {
-        bytes: &bytes,
-        queue: VecDeque::new(),
-        backlinks: vec![None; bytes.len()],
-        line_length: info.line_length,
-    };
-    for (i, &c) in bytes.iter().enumerate() {
-        if c == b'a' {
-            state.queue.push_back((i, i));
-        }
-    }
-    state.find_path(info.end_pos);
-    let steps = state.count_steps(info.end_pos);
-
-    println!("steps: {}", steps);
+/// Which steps between neighbouring cells are allowed and what they cost.
+/// The defaults describe the puzzle: climb at most one, descend any amount,
+/// and every step costs the same.
+#[derive(Clone, Copy, Debug)]
+struct ClimbingRules {
+    max_climb: u8,
+    max_descent: u8,
+    step_cost: usize,
+    // additional cost per unit of elevation gained or lost
+    climb_cost: usize,
+    descent_cost: usize,
+}
+
+impl Default for ClimbingRules {
+    fn default() -> Self {
+        ClimbingRules {
+            max_climb: 1,
+            max_descent: u8::MAX,
+            step_cost: 1,
+            climb_cost: 0,
+            descent_cost: 0,
+        }
+    }
+}
+
+impl ClimbingRules {
+    fn from_options(options: &Options) -> Self {
+        let default = ClimbingRules::default();
+        ClimbingRules {
+            max_climb: options.parse_or("--max-climb", default.max_climb),
+            max_descent: options.parse_or("--max-descent", default.max_descent),
+            step_cost: options.parse_or("--step-cost", default.step_cost),
+            climb_cost: options.parse_or("--climb-cost", default.climb_cost),
+            descent_cost: options.parse_or("--descent-cost", default.descent_cost),
+        }
+    }
+
+    /// Whether every step costs the same, making the cheapest route the
+    /// shortest one.
+    fn is_uniform(&self) -> bool {
+        self.climb_cost == 0 && self.descent_cost == 0
+    }
+
+    fn step_cost(&self, from: u8, to: u8) -> Option<usize> {
+        if to >= from {
+            let climb = to - from;
+            (climb <= self.max_climb).then(|| self.step_cost + self.climb_cost * climb as usize)
+        } else {
+            let descent = from - to;
+            (descent <= self.max_descent)
+                .then(|| self.step_cost + self.descent_cost * descent as usize)
+        }
+    }
 }
 
 struct State<'a> {
-    line_length: usize,
-    bytes: &'a [u8],
-    // (prev, pos)
-    queue: VecDeque<(usize, usize)>,
+    heights: &'a VecGrid<u8>,
+    rules: ClimbingRules,
+    // (cost, prev, pos), cheapest first
+    queue: BinaryHeap<Reverse<(usize, GridCoords, GridCoords)>>,
     // pos -> prev
-    backlinks: Vec<Option<usize>>,
+    backlinks: VecGrid<Option<GridCoords>>,
 }
 
 impl<'a> State<'a> {
-    fn visit_neighbours(&mut self, pos: usize) {
-        if pos % self.line_length > 0 {
-            self.visit_neighbour(pos, pos - 1);
+    fn new(heights: &'a VecGrid<u8>, rules: ClimbingRules) -> Self {
+        State {
+            heights,
+            rules,
+            queue: BinaryHeap::new(),
+            backlinks: VecGrid::full(heights.width(), heights.height(), None),
         }
-        if pos % self.line_length < self.line_length - 1 {
-            self.visit_neighbour(pos, pos + 1);
+    }
+
+    fn visit_neighbours(&mut self, cost: usize, (x, y): GridCoords) {
+        if x > 0 {
+            self.visit_neighbour(cost, (x, y), (x - 1, y));
         }
-        if pos > self.line_length {
-            self.visit_neighbour(pos, pos - self.line_length);
+        if x + 1 < self.heights.width() {
+            self.visit_neighbour(cost, (x, y), (x + 1, y));
         }
-        if pos < self.bytes.len() - self.line_length {
-            self.visit_neighbour(pos, pos + self.line_length);
+        if y > 0 {
+            self.visit_neighbour(cost, (x, y), (x, y - 1));
+        }
+        if y + 1 < self.heights.height() {
+            self.visit_neighbour(cost, (x, y), (x, y + 1));
         }
     }
 
-    fn visit_neighbour(&mut self, from: usize, pos: usize) {
-        if self.backlinks[pos].is_none() && self.bytes[pos] <= self.bytes[from] + 1 {
-            self.queue.push_back((from, pos))
+    fn visit_neighbour(&mut self, cost: usize, from: GridCoords, pos: GridCoords) {
+        if self.backlinks[pos].is_none() {
+            if let Some(step_cost) = self.rules.step_cost(self.heights[from], self.heights[pos]) {
+                self.queue.push(Reverse((cost + step_cost, from, pos)))
+            }
         }
     }
 
-    fn find_path(&mut self, end_pos: usize) {
-        while let Some((prev, pos)) = self.queue.pop_front() {
+    /// Runs Dijkstra's algorithm from the queued starts, returning the cost
+    /// of the cheapest route to the end.
+    fn find_path(&mut self, end: GridCoords) -> Option<usize> {
+        while let Some(Reverse((cost, prev, pos))) = self.queue.pop() {
             if self.backlinks[pos].is_none() {
                 self.backlinks[pos] = Some(prev);
-                if pos == end_pos {
-                    return;
+                if pos == end {
+                    return Some(cost);
                 } else {
-                    self.visit_neighbours(pos);
+                    self.visit_neighbours(cost, pos);
                 }
             }
         }
+        None
     }
 
-    fn count_steps(&mut self, target: usize) -> usize {
-        let mut steps = 0;
+    /// Follows the backlinks from the target back to where the search
+    /// started, returning the positions along the way in walking order.
+    fn path(&self, target: GridCoords) -> Option<Vec<GridCoords>> {
+        let mut path = vec![target];
         let mut pos = target;
         loop {
-            let prev = self.backlinks[pos].unwrap();
+            let prev = self.backlinks[pos]?;
             if prev == pos {
-                return steps;
+                path.reverse();
+                return Some(path);
             }
+            path.push(prev);
             pos = prev;
-            steps += 1;
         }
     }
 }
 
-#[derive(Debug)]
-struct Info {
-    line_length: usize,
-    start_pos: usize,
-    end_pos: usize,
+struct Heightmap {
+    // elevation above 'a'
+    heights: VecGrid<u8>,
+    start: GridCoords,
+    end: GridCoords,
 }
 
-fn read_info(bytes: &[u8]) -> Info {
-    let mut line_length = None;
-    let mut start_pos = None;
-    let mut end_pos = None;
-
-    for (i, c) in bytes.iter().enumerate() {
-        match c {
-            b'S' => start_pos = Some(i),
-            b'E' => end_pos = Some(i),
-            b'\n' => {
-                if line_length.is_none() {
-                    line_length = Some(i + 1);
+fn parse_heightmap(bytes: &[u8]) -> Heightmap {
+    let lines: Vec<&[u8]> = bytes
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .collect();
+    let width = lines.first().expect("empty heightmap").len();
+    let mut heights = VecGrid::full(width, lines.len(), 0);
+    let mut start = None;
+    let mut end = None;
+
+    for (y, line) in lines.iter().enumerate() {
+        assert_eq!(line.len(), width, "line {} has a different width", y + 1);
+        for (x, &c) in line.iter().enumerate() {
+            let elevation = match c {
+                b'S' => {
+                    start = Some((x, y));
+                    b'a'
                 }
-            }
-            _ => (),
+                b'E' => {
+                    end = Some((x, y));
+                    b'z'
+                }
+                b'a'..=b'z' => c,
+                _ => panic!("invalid elevation {:?}", c as char),
+            };
+            heights[(x, y)] = elevation - b'a';
         }
     }
 
-    Info {
-        line_length: line_length.unwrap(),
-        start_pos: start_pos.unwrap(),
-        end_pos: end_pos.unwrap(),
+    Heightmap {
+        heights,
+        start: start.expect("no start marker"),
+        end: end.expect("no end marker"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &[u8] = b"Sabqponm\nabcryxxl\naccszExk\nacctuvwj\nabdefghi\n";
+
+    #[test]
+    fn test_render_path() {
+        let map = parse_heightmap(EXAMPLE);
+        let mut state = State::new(&map.heights, ClimbingRules::default());
+        state.queue.push(Reverse((0, map.start, map.start)));
+        assert_eq!(state.find_path(map.end), Some(31));
+        let path = state.path(map.end).unwrap();
+
+        assert_eq!(path.len() - 1, 31);
+        assert_eq!(path[0], (0, 0));
+        assert_eq!(path[31], (5, 2));
+        // every step moves to an adjacent cell
+        for step in path.windows(2) {
+            let ((x0, y0), (x1, y1)) = (step[0], step[1]);
+            assert_eq!(x0.abs_diff(x1) + y0.abs_diff(y1), 1);
+        }
+
+        let rendered = render_path(&map, &path);
+        assert_eq!(rendered.matches(['>', '<', '^', 'v']).count(), 31);
+        assert_eq!(rendered.lines().nth(2).unwrap().as_bytes()[5], b'E');
+    }
+
+    fn cheapest(map: &[u8], rules: ClimbingRules) -> Option<(usize, usize)> {
+        let map = parse_heightmap(map);
+        let mut state = State::new(&map.heights, rules);
+        state.queue.push(Reverse((0, map.start, map.start)));
+        let cost = state.find_path(map.end)?;
+        Some((state.path(map.end)?.len() - 1, cost))
+    }
+
+    #[test]
+    fn test_grid_edges() {
+        let rules = ClimbingRules::default();
+        // start on the first row, end in the last column
+        assert_eq!(
+            cheapest(b"SbcdefghijklmnopqrstuvwxyE\n", rules),
+            Some((25, 25))
+        );
+        // the end on the first row is only reachable by climbing up column 0
+        let column: Vec<u8> = b"EyxwvutsrqponmlkjihgfedcbS"
+            .iter()
+            .flat_map(|&c| [c, b'\n'])
+            .collect();
+        assert_eq!(cheapest(&column, rules), Some((25, 25)));
+        // the line ends are not cells, so there is no way around the wall
+        assert_eq!(cheapest(b"zzS\nEzz", rules), None);
+    }
+
+    #[test]
+    fn test_climbing_rules() {
+        let map = b"SzaE\naaaa\n";
+        let steep = ClimbingRules {
+            max_climb: 25,
+            ..ClimbingRules::default()
+        };
+        assert_eq!(cheapest(map, ClimbingRules::default()), None);
+        assert_eq!(cheapest(map, steep), Some((3, 3)));
+
+        // climbing over the peak costs more than walking around it
+        let rules = ClimbingRules {
+            climb_cost: 10,
+            ..steep
+        };
+        assert_eq!(cheapest(map, rules), Some((5, 5 + 250)));
+
+        // descending at most one rules out going over the peak
+        let rules = ClimbingRules {
+            max_descent: 1,
+            ..steep
+        };
+        assert_eq!(cheapest(map, rules), Some((5, 5)));
     }
 }
@@ -45,4 +45,55 @@ impl<T> VecGrid<T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.inner.iter()
     }
+
+    /// Draws the grid as text, one character per cell.
+    pub fn render<F>(&self, mut cell_char: F) -> String
+    where
+        F: FnMut(&T) -> char,
+    {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in self.inner.chunks(self.width) {
+            out.extend(row.iter().map(&mut cell_char));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Encodes the grid as a binary PPM image, one pixel per cell.
+    pub fn to_ppm<F>(&self, mut cell_color: F) -> Vec<u8>
+    where
+        F: FnMut(&T) -> [u8; 3],
+    {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for cell in self.inner.iter() {
+            out.extend_from_slice(&cell_color(cell));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_and_ppm() {
+        let mut grid = VecGrid::full(3, 2, 0u8);
+        grid[(1, 0)] = 1;
+        grid[(2, 1)] = 2;
+
+        let text = grid.render(|&cell| [' ', '#', '@'][cell as usize]);
+        assert_eq!(text, " # \n  @\n");
+
+        let image = grid.to_ppm(|&cell| [cell * 100, 0, 255]);
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&image[..header.len()], header);
+        assert_eq!(
+            &image[header.len()..],
+            &[
+                0, 0, 255, 100, 0, 255, 0, 0, 255, //
+                0, 0, 255, 0, 0, 255, 200, 0, 255,
+            ]
+        );
+    }
 }
@@ -1,9 +1,13 @@
 mod grid;
 mod hashing;
+mod options;
 mod reader;
+mod top_k;
 mod vecs;
 
 pub use grid::*;
 pub use hashing::*;
+pub use options::*;
 pub use reader::*;
+pub use top_k::*;
 pub use vecs::*;
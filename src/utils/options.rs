@@ -0,0 +1,122 @@
+use std::str::FromStr;
+
+/// Extra command line flags, passed after the input path.
+/// Flags are either bare (`--trace`) or carry a value (`--break-cycle=20,60`).
+pub struct Options {
+    args: Vec<String>,
+}
+
+impl Options {
+    /// Panics on any flag not in `known`, where flags that carry a value
+    /// are listed with a trailing `=`, e.g. `&["--trace", "--width="]`.
+    pub fn from_args(args: &[String], known: &[&str]) -> Self {
+        for arg in args {
+            let is_known = match arg.split_once('=') {
+                Some((name, _)) => known
+                    .iter()
+                    .any(|flag| flag.strip_suffix('=') == Some(name)),
+                None => known.contains(&arg.as_str()),
+            };
+            if !is_known {
+                let name = arg.split('=').next().unwrap();
+                if known.contains(&name) {
+                    panic!("{} does not take a value", name);
+                } else if known
+                    .iter()
+                    .any(|flag| flag.strip_suffix('=') == Some(name))
+                {
+                    panic!("{} needs a value", name);
+                } else {
+                    panic!("unknown flag {}", name);
+                }
+            }
+        }
+
+        Options {
+            args: args.to_vec(),
+        }
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        let with_value = format!("{}=", name);
+        assert!(
+            !self.args.iter().any(|arg| arg.starts_with(&with_value)),
+            "{} does not take a value",
+            name
+        );
+        self.args.iter().any(|arg| arg == name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.args.iter().find_map(|arg| {
+            arg.strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('='))
+        })
+    }
+
+    pub fn parse_or<T>(&self, name: &str, default: T) -> T
+    where
+        T: FromStr,
+    {
+        match self.value(name) {
+            Some(value) => value
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid value for {}: {:?}", name, value)),
+            None => default,
+        }
+    }
+
+    /// Parse a comma-separated list, e.g. `--break-cycle=20,60,100`
+    pub fn parse_list<T>(&self, name: &str) -> Option<Vec<T>>
+    where
+        T: FromStr,
+    {
+        self.value(name).map(|value| {
+            value
+                .split(',')
+                .map(|item| {
+                    item.parse()
+                        .unwrap_or_else(|_| panic!("invalid value for {}: {:?}", name, item))
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KNOWN: &[&str] = &["--trace", "--width="];
+
+    fn options(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Options::from_args(&args, KNOWN)
+    }
+
+    #[test]
+    fn test_known_flags() {
+        let opts = options(&["--trace", "--width=5"]);
+        assert!(opts.flag("--trace"));
+        assert_eq!(opts.parse_or("--width", 40), 5);
+        assert_eq!(options(&[]).parse_or("--width", 40), 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown flag --trce")]
+    fn test_misspelled_flag() {
+        options(&["--trce"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "--trace does not take a value")]
+    fn test_bare_flag_with_value() {
+        options(&["--trace=1"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "--width needs a value")]
+    fn test_value_flag_without_value() {
+        options(&["--width"]);
+    }
+}
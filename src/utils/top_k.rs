@@ -0,0 +1,52 @@
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+/// Keeps track of the `k` largest items pushed into it, without storing
+/// the others.
+pub struct TopK<T> {
+    k: usize,
+    // min-heap, so that the smallest of the current top k is at the top
+    heap: BinaryHeap<Reverse<T>>,
+}
+
+impl<T: Ord> TopK<T> {
+    pub fn new(k: usize) -> Self {
+        TopK {
+            k,
+            heap: BinaryHeap::with_capacity(k + 1),
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        if self.heap.len() < self.k {
+            self.heap.push(Reverse(item));
+        } else if let Some(mut smallest) = self.heap.peek_mut() {
+            if item > smallest.0 {
+                smallest.0 = item;
+            }
+        }
+    }
+
+    /// The largest items, largest first
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        // sorting the Reverse-wrapped items ascending puts the largest first
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(item)| item)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_top_k() {
+        let mut top = TopK::new(3);
+        for value in [5, 1, 9, 3, 7, 9, 2] {
+            top.push(value);
+        }
+        assert_eq!(top.into_sorted_vec(), vec![9, 9, 7]);
+    }
+}